@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    judge::{BetKind, Variant},
+    money::Money,
+    table_config::TableConfig,
+    ws_messages::{BetSpot, ResponseMessages},
+};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+pub(crate) enum RejectReason {
+    UnknownLabel,
+    InvalidPlacement,
+    InvalidBet,
+    NonPositiveAmount,
+    InsufficientBalance,
+    BelowMinimum,
+    AboveMaximum,
+    AboveTableMaximum,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct BetRejection {
+    pub(crate) reason_code: RejectReason,
+    pub(crate) detail: Arc<str>,
+}
+
+impl BetRejection {
+    pub(crate) fn new(reason_code: RejectReason, detail: impl Into<Arc<str>>) -> Self {
+        Self {
+            reason_code,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl From<BetRejection> for ResponseMessages {
+    fn from(value: BetRejection) -> Self {
+        ResponseMessages::BetRejected {
+            reason_code: value.reason_code,
+            detail: value.detail,
+        }
+    }
+}
+
+/// Turns whatever the client sent into a bet the judge understands.
+pub(crate) fn resolve_spot(spot: BetSpot, variant: Variant) -> Result<BetKind, BetRejection> {
+    let kind = match spot {
+        BetSpot::Kind { kind } => kind,
+        BetSpot::Board {
+            label,
+            placement,
+            local_position,
+        } => BetKind::from_board(&label, placement, local_position)?,
+    };
+
+    if !kind.is_valid(variant) {
+        return Err(BetRejection::new(
+            RejectReason::InvalidBet,
+            format!("{:?} is not a bet on {:?} tables", kind, variant),
+        ));
+    }
+    Ok(kind)
+}
+
+/// Checks that `amount` can be staked on `kind` on top of the `placed` stakes
+/// within the table's limits and returns the new total stake.
+pub(crate) fn check_stake(
+    kind: &BetKind,
+    amount: Money,
+    placed: &[(BetKind, Money)],
+    balance: Money,
+    config: &TableConfig,
+) -> Result<Money, BetRejection> {
+    if !amount.is_positive() {
+        return Err(BetRejection::new(
+            RejectReason::NonPositiveAmount,
+            format!("Bet amount must be positive, got {}", amount),
+        ));
+    }
+
+    if let Some(limit) = config.limit(kind.bet_type()) {
+        let on_spot = Money::checked_sum(
+            placed
+                .iter()
+                .filter(|(placed_kind, _)| placed_kind == kind)
+                .map(|(_, amount)| *amount),
+        )
+        .and_then(|on_spot| on_spot.checked_add(amount))
+        .map_err(|e| BetRejection::new(RejectReason::AboveMaximum, e.to_string()))?;
+        if let Some(min) = limit.min.filter(|min| on_spot < *min) {
+            return Err(BetRejection::new(
+                RejectReason::BelowMinimum,
+                format!(
+                    "{:?} bets need at least {}, got {}",
+                    kind.bet_type(),
+                    min,
+                    on_spot
+                ),
+            ));
+        }
+        if let Some(max) = limit.max.filter(|max| on_spot > *max) {
+            return Err(BetRejection::new(
+                RejectReason::AboveMaximum,
+                format!(
+                    "{:?} bets take at most {}, got {}",
+                    kind.bet_type(),
+                    max,
+                    on_spot
+                ),
+            ));
+        }
+    }
+
+    let total_bet = Money::checked_sum(placed.iter().map(|(_, amount)| *amount))
+        .and_then(|total| total.checked_add(amount))
+        .ok()
+        .filter(|total_bet| *total_bet <= balance)
+        .ok_or_else(|| {
+            BetRejection::new(
+                RejectReason::InsufficientBalance,
+                format!("Balance of {} can't cover another {}", balance, amount),
+            )
+        })?;
+
+    if let Some(table_max) = config.table_max.filter(|table_max| total_bet > *table_max) {
+        return Err(BetRejection::new(
+            RejectReason::AboveTableMaximum,
+            format!(
+                "Table takes at most {} per round, got {}",
+                table_max, total_bet
+            ),
+        ));
+    }
+    Ok(total_bet)
+}
@@ -0,0 +1,46 @@
+use rocket::{
+    form::FromFormField,
+    serde::json::{self, Value},
+};
+use rocket_ws::Message;
+
+/// How messages are put into websocket frames, picked with the `encoding`
+/// query parameter when connecting. Either way they are laid out as the
+/// connection's `Protocol` says.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub(crate) enum Encoding {
+    /// Text frames.
+    #[default]
+    Json,
+    /// Binary frames, maps keyed by field name.
+    #[field(value = "msgpack")]
+    MessagePack,
+}
+
+impl Encoding {
+    /// The message in `message`, or `None` for frames that don't carry one.
+    pub(crate) fn read(&self, message: Message) -> Option<anyhow::Result<Value>> {
+        let value = match (self, message) {
+            (Encoding::Json, Message::Text(text)) => json::from_str(&text).map_err(Into::into),
+            (Encoding::MessagePack, Message::Binary(bytes)) => {
+                rmp_serde::from_slice(&bytes).map_err(Into::into)
+            }
+            (Encoding::Json, Message::Binary(_)) => {
+                Err(anyhow::anyhow!("Expected JSON in text frames"))
+            }
+            (Encoding::MessagePack, Message::Text(_)) => {
+                Err(anyhow::anyhow!("Expected MessagePack in binary frames"))
+            }
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    pub(crate) fn write(&self, value: &Value) -> anyhow::Result<Message> {
+        let message = match self {
+            Encoding::Json => Message::Text(json::to_string(value)?),
+            Encoding::MessagePack => Message::Binary(rmp_serde::to_vec_named(value)?),
+        };
+        Ok(message)
+    }
+}
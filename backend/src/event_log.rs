@@ -0,0 +1,99 @@
+use std::collections::VecDeque;
+
+use crate::{
+    structs::PlayerId,
+    ws_messages::{OutboundMessage, ResponseMessages},
+};
+
+/// Sequence number of a table event. Numbering starts from the time the table
+/// was opened, so it keeps growing when a closed table is opened again.
+pub(crate) type Seq = u64;
+
+const EVENT_LOG_CAPACITY: usize = 256;
+
+/// Who an event was sent to.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Audience {
+    Everyone,
+    Except(PlayerId),
+    Only(PlayerId),
+}
+
+#[derive(Debug)]
+struct LoggedEvent {
+    seq: Seq,
+    audience: Audience,
+    message: ResponseMessages,
+}
+
+/// The latest events of a table, kept so a player who lost their connection
+/// can catch up on what they missed.
+#[derive(Debug)]
+pub(crate) struct EventLog {
+    last_seq: Seq,
+    events: VecDeque<LoggedEvent>,
+}
+
+impl Audience {
+    fn includes(&self, player_id: &PlayerId) -> bool {
+        match self {
+            Audience::Everyone => true,
+            Audience::Except(except) => except != player_id,
+            Audience::Only(only) => only == player_id,
+        }
+    }
+}
+
+impl EventLog {
+    pub(crate) fn new() -> Self {
+        Self {
+            last_seq: chrono::offset::Utc::now().timestamp_micros() as Seq,
+            events: VecDeque::with_capacity(EVENT_LOG_CAPACITY),
+        }
+    }
+
+    pub(crate) fn last_seq(&self) -> Seq {
+        self.last_seq
+    }
+
+    /// Numbers `message`, keeps it and returns it ready to send.
+    pub(crate) fn record(
+        &mut self,
+        audience: Audience,
+        message: ResponseMessages,
+    ) -> OutboundMessage {
+        self.last_seq += 1;
+        if self.events.len() == EVENT_LOG_CAPACITY {
+            self.events.pop_front();
+        }
+        self.events.push_back(LoggedEvent {
+            seq: self.last_seq,
+            audience,
+            message: message.clone(),
+        });
+        OutboundMessage::event(self.last_seq, message)
+    }
+
+    /// Events after `last_seq` that were sent to `player_id`, or `None` when
+    /// the log no longer goes back that far.
+    pub(crate) fn since(
+        &self,
+        last_seq: Seq,
+        player_id: &PlayerId,
+    ) -> Option<Vec<OutboundMessage>> {
+        let oldest = self
+            .events
+            .front()
+            .map_or(self.last_seq + 1, |event| event.seq);
+        if last_seq > self.last_seq || last_seq + 1 < oldest {
+            return None;
+        }
+        Some(
+            self.events
+                .iter()
+                .filter(|event| event.seq > last_seq && event.audience.includes(player_id))
+                .map(|event| OutboundMessage::event(event.seq, event.message.clone()))
+                .collect(),
+        )
+    }
+}
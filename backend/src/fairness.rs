@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    judge::{Pocket, Variant},
+    ws_messages::SpinProof,
+};
+
+pub(crate) type ServerSeed = [u8; 32];
+
+const MAX_CLIENT_SEED_LENGTH: usize = 64;
+
+/// Secret behind one round. Its hash is published before the spin and the
+/// seed itself right after, so players can check the number wasn't picked
+/// after the fact.
+#[derive(Debug)]
+pub(crate) struct FairRound {
+    server_seed: ServerSeed,
+    nonce: u64,
+}
+
+impl FairRound {
+    pub(crate) fn new(server_seed: ServerSeed, nonce: u64) -> Self {
+        Self { server_seed, nonce }
+    }
+
+    pub(crate) fn next(&self, server_seed: ServerSeed) -> Self {
+        Self::new(server_seed, self.nonce + 1)
+    }
+
+    pub(crate) fn server_seed(&self) -> &ServerSeed {
+        &self.server_seed
+    }
+
+    pub(crate) fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    pub(crate) fn commitment(&self) -> Arc<str> {
+        commitment_of(&self.server_seed)
+    }
+
+    pub(crate) fn proof(&self, client_seeds: Vec<Arc<str>>, variant: Variant) -> SpinProof {
+        SpinProof {
+            server_seed: hex::encode(self.server_seed).into(),
+            server_seed_hash: self.commitment(),
+            client_seeds,
+            nonce: self.nonce,
+            variant,
+        }
+    }
+}
+
+pub(crate) fn commitment_of(server_seed: &[u8]) -> Arc<str> {
+    hex::encode(Sha256::digest(server_seed)).into()
+}
+
+pub(crate) fn is_valid_client_seed(client_seed: &str) -> bool {
+    !client_seed.is_empty()
+        && client_seed.len() <= MAX_CLIENT_SEED_LENGTH
+        && client_seed
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// HMAC-SHA256 keyed with the server seed over `client_seeds` joined by `,`
+/// followed by `:` and the nonce. The digest is read as big endian `u32`s and
+/// the first one below the largest multiple of `pockets` picks the pocket; if
+/// none qualifies the digest is fed back in as the message.
+pub(crate) fn derive_pocket(
+    server_seed: &[u8],
+    client_seeds: &[Arc<str>],
+    nonce: u64,
+    pockets: u32,
+) -> Pocket {
+    let limit = u32::MAX - u32::MAX % pockets;
+    let mut message = format!("{}:{}", client_seeds.join(","), nonce).into_bytes();
+    loop {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(server_seed).expect("HMAC accepts keys of any size");
+        mac.update(&message);
+        let digest = mac.finalize().into_bytes();
+
+        let pocket = digest
+            .chunks_exact(4)
+            .map(|chunk| u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .find(|value| *value < limit);
+        if let Some(value) = pocket {
+            return value % pockets;
+        }
+        message = digest.to_vec();
+    }
+}
+
+/// Recomputes a past spin from its revealed proof.
+pub(crate) fn verify(
+    server_seed: &str,
+    client_seeds: &[Arc<str>],
+    nonce: u64,
+    pockets: u32,
+) -> anyhow::Result<(Pocket, Arc<str>)> {
+    let server_seed = hex::decode(server_seed)?;
+    Ok((
+        derive_pocket(&server_seed, client_seeds, nonce, pockets),
+        commitment_of(&server_seed),
+    ))
+}
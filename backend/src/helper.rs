@@ -1,28 +1,34 @@
-use std::{collections::HashMap, sync::Arc};
+use std::time::Duration;
 
-use anyhow::Ok;
-use rocket::tokio::sync::Mutex;
+use rocket::tokio::sync::mpsc::{error::TrySendError, Sender};
 
-use crate::{
-    structs::{Player, PlayerId},
-    ws_messages::ResponseMessages,
-};
+use crate::ws_messages::OutboundMessage;
 
-pub(crate) async fn broadcast_response_message(
-    players: Arc<Mutex<HashMap<PlayerId, Player>>>,
-    except: Option<Vec<PlayerId>>,
-    response_message: ResponseMessages,
-) -> anyhow::Result<()> {
-    let players_ref = players.lock().await;
-    let except = except.unwrap_or(Vec::new());
-    for (player_id, player) in players_ref.iter() {
-        if player.ws_channel_sender.is_closed() || except.contains(player_id) {
-            continue;
+/// How many messages can wait on one connection.
+pub(crate) const CONNECTION_QUEUE_CAPACITY: usize = 64;
+
+/// Connections are pinged every `interval`. A player who hasn't been heard from
+/// since the last ping is away, and the connection is dropped once it has been
+/// quiet for `idle_timeout`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Heartbeat {
+    pub(crate) interval: Duration,
+    pub(crate) idle_timeout: Duration,
+}
+
+/// A table never waits on a player's connection: if their queue is full the
+/// message is dropped and they have to ask for the state again.
+pub(crate) fn send_response_message(
+    ws_channel_sender: &Sender<OutboundMessage>,
+    response_message: impl Into<OutboundMessage>,
+) {
+    match ws_channel_sender.try_send(response_message.into()) {
+        Ok(()) | Err(TrySendError::Closed(_)) => {}
+        Err(TrySendError::Full(response_message)) => {
+            log::warn!(
+                "Dropped a message for a slow connection: {:?}",
+                response_message
+            );
         }
-        player
-            .ws_channel_sender
-            .send(response_message.clone())
-            .await?;
     }
-    Ok(())
 }
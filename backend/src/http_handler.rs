@@ -0,0 +1,84 @@
+use rocket::{http::Status, response::status::BadRequest, serde::json::Json, State};
+
+use crate::{
+    session::{Session, SessionKeys, SessionToken},
+    statistics::{self, Statistics},
+    storage,
+    structs::{SpinResult, TableId, RECENT_LUCKY_NUMBERS, SPIN_HISTORY},
+    ws_messages::{VerifySpinRequest, VerifySpinResult},
+    ArcGame,
+};
+
+#[post("/verify_spin", data = "<proof>")]
+pub(crate) fn verify_spin(
+    proof: Json<VerifySpinRequest>,
+) -> Result<Json<VerifySpinResult>, BadRequest<String>> {
+    proof
+        .verify()
+        .map(Json)
+        .map_err(|e| BadRequest(e.to_string()))
+}
+
+/// Hands out a token for a new guest player.
+#[post("/session/guest")]
+pub(crate) fn guest_session(keys: &State<SessionKeys>) -> Json<SessionToken> {
+    Json(keys.guest())
+}
+
+/// Swaps a still valid token for a fresh one with the same player.
+#[post("/session/refresh")]
+pub(crate) fn refresh_session(session: Session, keys: &State<SessionKeys>) -> Json<SessionToken> {
+    Json(keys.issue(session.player_id))
+}
+
+/// Numbers drawn at a table over its last `spins` spins, 100 unless asked.
+#[get("/tables/<table_id>/stats?<spins>")]
+pub(crate) async fn table_statistics(
+    table_id: TableId,
+    spins: Option<u32>,
+    game: &State<ArcGame>,
+) -> Result<Json<Statistics>, Status> {
+    let statistics = storage::blocking(&game.storage, move |storage| {
+        match storage.variant(&table_id)? {
+            Some(variant) => {
+                statistics::table_statistics(storage, &table_id, variant, spins).map(Some)
+            }
+            None => Ok(None),
+        }
+    })
+    .await;
+    match statistics {
+        Ok(Some(statistics)) => Ok(Json(statistics)),
+        Ok(None) => Err(Status::NotFound),
+        Err(e) => {
+            log::error!("{:?}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[get("/tables/<table_id>/history?<limit>")]
+pub(crate) async fn table_history(
+    table_id: TableId,
+    limit: Option<u32>,
+    game: &State<ArcGame>,
+) -> Result<Json<Vec<SpinResult>>, Status> {
+    let limit = limit
+        .unwrap_or(RECENT_LUCKY_NUMBERS as u32)
+        .clamp(1, SPIN_HISTORY as u32);
+    let history = storage::blocking(&game.storage, move |storage| {
+        match storage.variant(&table_id)? {
+            Some(_) => storage.spin_history(&table_id, limit).map(Some),
+            None => Ok(None),
+        }
+    })
+    .await;
+    match history {
+        Ok(Some(history)) => Ok(Json(history)),
+        Ok(None) => Err(Status::NotFound),
+        Err(e) => {
+            log::error!("{:?}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
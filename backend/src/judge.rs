@@ -1,4 +1,11 @@
-use crate::structs;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bet_validator::{BetRejection, RejectReason},
+    money::Money,
+    structs,
+};
 
 use self::structs::{Bet, Placement};
 
@@ -6,148 +13,672 @@ const RED: &str = "red";
 const BLACK: &str = "black";
 type BoxType = &'static str;
 
+pub(crate) type Pocket = u32;
+
+/// The "00" pocket of American wheels.
+pub(crate) const DOUBLE_ZERO: Pocket = 37;
+
 const BOX_COLOR_MAP: [BoxType; 36] = [
     RED, BLACK, RED, BLACK, RED, BLACK, RED, BLACK, RED, BLACK, BLACK, RED, BLACK, RED, BLACK, RED,
     BLACK, RED, RED, BLACK, RED, BLACK, RED, BLACK, RED, BLACK, RED, BLACK, BLACK, RED, BLACK, RED,
     BLACK, RED, BLACK, RED,
 ];
 
-const BOX_SIZE: i32 = 10; // Used for 0 as it shares edge with 3 blocks
+const BOX_SIZE: i32 = 70; // Used for 0 as it shares edge with 3 blocks
+const BOARD_COLUMNS: i32 = 12;
+const BOARD_ROWS: i32 = 3;
 
 pub(crate) struct Judgement {
-    pub(crate) winning_amount: i32,
-    pub(crate) bet_amount: i32,
+    pub(crate) winning_amount: Money,
+    pub(crate) bet_amount: Money,
+    pub(crate) settlements: Vec<Settlement>,
+}
+
+/// What a single bet staked and got back, stake included.
+#[derive(Debug, Clone)]
+pub(crate) struct Settlement {
+    pub(crate) kind: BetKind,
+    pub(crate) amount: Money,
+    pub(crate) returned: Money,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, JsonSchema)]
+pub(crate) enum PocketColor {
+    Red,
+    Black,
+    /// 0 and 00.
+    Green,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+pub(crate) enum Variant {
+    #[default]
+    European,
+    /// Adds the "00" pocket, numbered 37.
+    American,
+    /// European wheel with a rule softening the loss of even-money bets on 0.
+    French { rule: ZeroRule },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+pub(crate) enum ZeroRule {
+    /// Half of the stake is returned.
+    LaPartage,
+    /// The stake stays on the table for the next spin and is returned, without
+    /// winnings, if that spin wins the bet.
+    EnPrison,
+}
+
+impl Variant {
+    pub(crate) fn pockets(&self) -> u32 {
+        match self {
+            Variant::American => 38,
+            Variant::European | Variant::French { .. } => 37,
+        }
+    }
+}
+
+/// Every bet the table understands, identified by what it covers rather than
+/// where the chip was dropped on the board.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema)]
+pub(crate) enum BetKind {
+    Straight {
+        number: Pocket,
+    },
+    Split {
+        numbers: [Pocket; 2],
+    },
+    /// Three numbers of one street, identified by its lowest number.
+    Street {
+        first: Pocket,
+    },
+    /// 0-1-2 or 0-2-3, and 0-00-2 or 00-2-3 on American wheels.
+    Trio {
+        numbers: [Pocket; 3],
+    },
+    /// Four numbers sharing a corner, identified by the lowest number.
+    Corner {
+        first: Pocket,
+    },
+    /// Two adjacent streets, identified by the lowest number.
+    SixLine {
+        first: Pocket,
+    },
+    /// 0-1-2-3, not on American wheels.
+    TopLine,
+    /// 0-00-1-2-3, only on American wheels.
+    Basket,
+    /// Column 1 is 1, 4, 7, ..., 34.
+    Column {
+        column: u32,
+    },
+    /// Dozen 1 is 1-12.
+    Dozen {
+        dozen: u32,
+    },
+    Red,
+    Black,
+    Even,
+    Odd,
+    Low,
+    High,
+}
+
+/// A bet without the numbers it sits on, which is what table limits are set
+/// against.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+pub(crate) enum BetType {
+    Straight,
+    Split,
+    Street,
+    Trio,
+    Corner,
+    SixLine,
+    TopLine,
+    Basket,
+    Column,
+    Dozen,
+    Red,
+    Black,
+    Even,
+    Odd,
+    Low,
+    High,
+}
+
+impl BetKind {
+    pub(crate) fn is_valid(&self, variant: Variant) -> bool {
+        let american = variant == Variant::American;
+        match self {
+            BetKind::Straight { number } => *number < variant.pockets(),
+            BetKind::Split { numbers: [a, b] } => {
+                let (a, b) = (*a.min(b), *a.max(b));
+                match (a, b) {
+                    (_, DOUBLE_ZERO) => american && [0, 2, 3].contains(&a),
+                    (0, _) if american => (1..=2).contains(&b),
+                    (0, _) => (1..=3).contains(&b),
+                    _ => b <= 36 && (b == a + 3 || (b == a + 1 && a % 3 != 0)),
+                }
+            }
+            BetKind::Street { first } => (1..=34).contains(first) && first % 3 == 1,
+            BetKind::Trio { numbers } => {
+                let mut numbers = *numbers;
+                numbers.sort();
+                match american {
+                    true => {
+                        [[0, 1, 2], [0, 2, DOUBLE_ZERO], [2, 3, DOUBLE_ZERO]].contains(&numbers)
+                    }
+                    false => numbers == [0, 1, 2] || numbers == [0, 2, 3],
+                }
+            }
+            BetKind::Corner { first } => (1..=32).contains(first) && first % 3 != 0,
+            BetKind::SixLine { first } => (1..=31).contains(first) && first % 3 == 1,
+            BetKind::Column { column } => (1..=3).contains(column),
+            BetKind::Dozen { dozen } => (1..=3).contains(dozen),
+            BetKind::TopLine => !american,
+            BetKind::Basket => american,
+            BetKind::Red
+            | BetKind::Black
+            | BetKind::Even
+            | BetKind::Odd
+            | BetKind::Low
+            | BetKind::High => true,
+        }
+    }
+
+    /// Pockets that win this bet. Empty for invalid bets.
+    pub(crate) fn covered(&self, variant: Variant) -> Vec<Pocket> {
+        if !self.is_valid(variant) {
+            return vec![];
+        }
+        match self {
+            BetKind::Straight { number } => vec![*number],
+            BetKind::Split { numbers } => numbers.to_vec(),
+            BetKind::Street { first } => (*first..first + 3).collect(),
+            BetKind::Trio { numbers } => numbers.to_vec(),
+            BetKind::Corner { first } => vec![*first, first + 1, first + 3, first + 4],
+            BetKind::SixLine { first } => (*first..first + 6).collect(),
+            BetKind::TopLine => (0..=3).collect(),
+            BetKind::Basket => vec![0, 1, 2, 3, DOUBLE_ZERO],
+            BetKind::Column { column } => (*column..=36).step_by(3).collect(),
+            BetKind::Dozen { dozen } => ((dozen - 1) * 12 + 1..=dozen * 12).collect(),
+            BetKind::Red => numbers_of_color(RED),
+            BetKind::Black => numbers_of_color(BLACK),
+            BetKind::Even => (2..=36).step_by(2).collect(),
+            BetKind::Odd => (1..=36).step_by(2).collect(),
+            BetKind::Low => (1..=18).collect(),
+            BetKind::High => (19..=36).collect(),
+        }
+    }
+
+    /// Winnings per unit staked, not counting the returned stake.
+    ///
+    /// Set by the type of bet alone. Paid out with the stake, it is 36 units
+    /// spread over the pockets covered for every bet but the basket, which
+    /// gets 35 over 5, so the house keeps only what the zeros win.
+    pub(crate) fn payout(&self) -> i64 {
+        match self {
+            BetKind::Straight { .. } => 35,
+            BetKind::Split { .. } => 17,
+            BetKind::Street { .. } | BetKind::Trio { .. } => 11,
+            BetKind::Corner { .. } | BetKind::TopLine => 8,
+            BetKind::Basket => 6,
+            BetKind::SixLine { .. } => 5,
+            BetKind::Column { .. } | BetKind::Dozen { .. } => 2,
+            BetKind::Red
+            | BetKind::Black
+            | BetKind::Even
+            | BetKind::Odd
+            | BetKind::Low
+            | BetKind::High => 1,
+        }
+    }
+
+    pub(crate) fn is_even_money(&self) -> bool {
+        self.payout() == 1
+    }
+
+    pub(crate) fn bet_type(&self) -> BetType {
+        match self {
+            BetKind::Straight { .. } => BetType::Straight,
+            BetKind::Split { .. } => BetType::Split,
+            BetKind::Street { .. } => BetType::Street,
+            BetKind::Trio { .. } => BetType::Trio,
+            BetKind::Corner { .. } => BetType::Corner,
+            BetKind::SixLine { .. } => BetType::SixLine,
+            BetKind::TopLine => BetType::TopLine,
+            BetKind::Basket => BetType::Basket,
+            BetKind::Column { .. } => BetType::Column,
+            BetKind::Dozen { .. } => BetType::Dozen,
+            BetKind::Red => BetType::Red,
+            BetKind::Black => BetType::Black,
+            BetKind::Even => BetType::Even,
+            BetKind::Odd => BetType::Odd,
+            BetKind::Low => BetType::Low,
+            BetKind::High => BetType::High,
+        }
+    }
+
+    /// Translates a chip dropped on the frontend board into a bet.
+    ///
+    /// The board draws numbers column by column with 1 on the top row, so the
+    /// top and bottom edges of a column are streets and its outer corners are
+    /// six lines.
+    pub(crate) fn from_board(
+        label: &str,
+        placement: Placement,
+        local_position: (i32, i32),
+    ) -> Result<Self, BetRejection> {
+        let invalid_placement = || {
+            BetRejection::new(
+                RejectReason::InvalidPlacement,
+                format!("{:?} of {} is not a bet", placement, label),
+            )
+        };
+
+        let (width, height) = board_box_size(label).ok_or_else(|| {
+            BetRejection::new(
+                RejectReason::UnknownLabel,
+                format!("{} is not on the board", label),
+            )
+        })?;
+        if !(0..=width).contains(&local_position.0) || !(0..=height).contains(&local_position.1) {
+            return Err(BetRejection::new(
+                RejectReason::InvalidPlacement,
+                format!("{:?} is outside of {}", local_position, label),
+            ));
+        }
+
+        // The board only splits 0 along its right edge, anywhere else on the
+        // box is 0 itself.
+        if label == "0" {
+            return match placement {
+                Placement::Right => {
+                    let row = (local_position.1 / BOX_SIZE).min(BOARD_ROWS - 1);
+                    Ok(BetKind::Split {
+                        numbers: [0, row as Pocket + 1],
+                    })
+                }
+                _ => Ok(BetKind::Straight { number: 0 }),
+            };
+        }
+
+        if let Ok(number) = label.parse::<i32>() {
+            return from_board_cell(
+                (number - 1) / BOARD_ROWS,
+                (number - 1) % BOARD_ROWS,
+                placement,
+            )
+            .ok_or_else(invalid_placement);
+        }
+
+        if placement != Placement::Center {
+            return Err(invalid_placement());
+        }
+        let kind = match label {
+            "3rd" => BetKind::Column { column: 1 },
+            "2nd" => BetKind::Column { column: 2 },
+            "1st" => BetKind::Column { column: 3 },
+            "1-12" => BetKind::Dozen { dozen: 1 },
+            "13-24" => BetKind::Dozen { dozen: 2 },
+            "25-36" => BetKind::Dozen { dozen: 3 },
+            "1-18" => BetKind::Low,
+            "19-36" => BetKind::High,
+            "even" => BetKind::Even,
+            "odd" => BetKind::Odd,
+            "red" => BetKind::Red,
+            "black" => BetKind::Black,
+            _ => unreachable!("board_box_size knows every label"),
+        };
+        Ok(kind)
+    }
 }
 
-fn sanitize(num: i32) -> String {
-    match num {
-        n if n < 0 => "0".to_string(),
-        n if n > 36 => "".to_string(),
-        n => n.to_string(),
+/// Size of the box drawn for `label` on the frontend board.
+fn board_box_size(label: &str) -> Option<(i32, i32)> {
+    let size = match label {
+        "0" => (BOX_SIZE, BOARD_ROWS * BOX_SIZE),
+        "3rd" | "2nd" | "1st" => (BOX_SIZE, BOX_SIZE),
+        "1-12" | "13-24" | "25-36" => (4 * BOX_SIZE, BOX_SIZE),
+        "1-18" | "19-36" | "even" | "odd" | "red" | "black" => (2 * BOX_SIZE, BOX_SIZE),
+        label => match label.parse::<i32>() {
+            Ok(number) if (1..=36).contains(&number) && number.to_string() == label => {
+                (BOX_SIZE, BOX_SIZE)
+            }
+            _ => return None,
+        },
+    };
+    Some(size)
+}
+
+pub(crate) fn pocket_color(pocket: Pocket) -> PocketColor {
+    match BOX_COLOR_MAP.get((pocket as usize).wrapping_sub(1)) {
+        Some(&RED) => PocketColor::Red,
+        Some(_) => PocketColor::Black,
+        None => PocketColor::Green,
     }
 }
 
-fn range(start: i32, stop: i32, step: i32) -> Vec<String> {
-    (start..stop)
-        .step_by(step as usize)
-        .map(|x| x.to_string())
+fn numbers_of_color(color: BoxType) -> Vec<Pocket> {
+    BOX_COLOR_MAP
+        .iter()
+        .enumerate()
+        .filter(|(_, &c)| c == color)
+        .map(|(index, _)| index as Pocket + 1)
         .collect()
 }
 
-fn get_affected_by_bet(bet: &Bet) -> Vec<String> {
-    let mut result = vec![bet.label.clone()];
-
-    if bet.label == "0" {
-        if bet.placement == Placement::Right {
-            let y = (bet.local_position.1 / BOX_SIZE) + 1;
-            result.push(y.to_string());
-        }
-        return result;
-    }
-
-    if bet.label.chars().all(|c| c.is_numeric()) {
-        let label_number: i32 = bet.label.parse().unwrap_or(0);
-
-        match label_number % 3 {
-            1 => match bet.placement {
-                Placement::TopLeft => result.extend(vec![
-                    sanitize(label_number - 3),
-                    sanitize(label_number - 2),
-                    sanitize(label_number - 1),
-                    sanitize(label_number + 1),
-                    sanitize(label_number + 2),
-                ]),
-                Placement::TopRight => result.extend(vec![
-                    sanitize(label_number + 1),
-                    sanitize(label_number + 2),
-                    sanitize(label_number + 3),
-                    sanitize(label_number + 4),
-                    sanitize(label_number + 5),
-                ]),
-                _ => {}
-            },
-            2 => match bet.placement {
-                Placement::TopLeft => result.extend(vec![
-                    sanitize(label_number - 4),
-                    sanitize(label_number - 3),
-                    sanitize(label_number - 1),
-                ]),
-                Placement::BottomRight => result.extend(vec![
-                    sanitize(label_number + 1),
-                    sanitize(label_number + 3),
-                    sanitize(label_number + 4),
-                ]),
-                _ => {}
-            },
-            0 => match bet.placement {
-                Placement::TopLeft => result.extend(vec![
-                    sanitize(label_number - 4),
-                    sanitize(label_number - 3),
-                    sanitize(label_number - 1),
-                ]),
-                Placement::BottomRight => result.extend(vec![
-                    sanitize(label_number - 2),
-                    sanitize(label_number - 1),
-                    sanitize(label_number + 1),
-                    sanitize(label_number + 2),
-                    sanitize(label_number + 3),
-                ]),
-                _ => {}
-            },
-            _ => {}
-        }
-        return result;
-    }
-
-    match bet.label.as_str() {
-        "3rd" => range(1, 37, 3),
-        "2nd" => range(2, 37, 3),
-        "1st" => range(3, 37, 3),
-        "1-12" => range(1, 13, 1),
-        "13-24" => range(13, 25, 1),
-        "25-36" => range(25, 37, 1),
-        "1-18" => range(1, 19, 1),
-        "19-36" => range(19, 37, 1),
-        "even" => range(2, 37, 2),
-        "odd" => range(1, 37, 2),
-        "red" => BOX_COLOR_MAP
-            .iter()
-            .enumerate()
-            .filter(|(_, &color)| color == RED)
-            .map(|(index, _)| (index + 1).to_string())
-            .collect(),
-        "black" => BOX_COLOR_MAP
-            .iter()
-            .enumerate()
-            .filter(|(_, &color)| color == BLACK)
-            .map(|(index, _)| (index + 1).to_string())
-            .collect(),
-        _ => vec![],
-    }
-}
-
-pub(crate) async fn judge_player(bets: &Vec<Bet>, lucky_number: u32) -> Judgement {
-    let mut winning_amount = 0;
-    let mut bet_amount = 0;
+fn cell_number(column: i32, row: i32) -> Pocket {
+    (column * BOARD_ROWS + row + 1) as Pocket
+}
+
+/// Bet formed by a chip on `placement` of the numbered cell at `column`, `row`.
+/// Column -1 is the 0 box which spans every row.
+fn from_board_cell(column: i32, row: i32, placement: Placement) -> Option<BetKind> {
+    let (d_column, d_row) = match placement {
+        Placement::Center => {
+            return Some(BetKind::Straight {
+                number: cell_number(column, row),
+            })
+        }
+        Placement::Left => (-1, 0),
+        Placement::Right => (1, 0),
+        Placement::Top => (0, -1),
+        Placement::Bottom => (0, 1),
+        Placement::TopLeft => (-1, -1),
+        Placement::TopRight => (1, -1),
+        Placement::BottomLeft => (-1, 1),
+        Placement::BottomRight => (1, 1),
+    };
+
+    let other_column = column + d_column;
+    let other_row = row + d_row;
+    if other_column >= BOARD_COLUMNS {
+        return None;
+    }
+    let outer_edge = !(0..BOARD_ROWS).contains(&other_row);
+
+    let kind = match (d_column, outer_edge) {
+        (0, true) => BetKind::Street {
+            first: cell_number(column, 0),
+        },
+        (0, false) => split(cell_number(column, row), cell_number(column, other_row)),
+        (_, true) if other_column < 0 => BetKind::TopLine,
+        (_, true) => BetKind::SixLine {
+            first: cell_number(column.min(other_column), 0),
+        },
+        (_, false) if other_column < 0 && d_row == 0 => split(0, cell_number(column, row)),
+        (_, false) if d_row == 0 => split(cell_number(column, row), cell_number(other_column, row)),
+        (_, false) if other_column < 0 => {
+            let mut numbers = [0, cell_number(column, row), cell_number(column, other_row)];
+            numbers.sort();
+            BetKind::Trio { numbers }
+        }
+        (_, false) => BetKind::Corner {
+            first: cell_number(column.min(other_column), row.min(other_row)),
+        },
+    };
+    Some(kind)
+}
+
+fn split(a: Pocket, b: Pocket) -> BetKind {
+    BetKind::Split {
+        numbers: [a.min(b), a.max(b)],
+    }
+}
+
+/// Settles `bets` against `lucky_number`, moving even-money bets in and out of
+/// prison on French tables playing En Prison.
+pub(crate) async fn judge_player(
+    bets: &mut [Bet],
+    lucky_number: Pocket,
+    variant: Variant,
+) -> anyhow::Result<Judgement> {
+    let mut settlements = Vec::with_capacity(bets.len());
     for bet in bets {
-        bet_amount += bet.amount;
-        let affected = get_affected_by_bet(bet);
-        if affected.contains(&lucky_number.to_string()) {
-            winning_amount += match affected.len() {
-                18 => 1 * bet.amount,
-                12 => 2 * bet.amount,
-                6 => 5 * bet.amount,
-                4 => 8 * bet.amount,
-                3 => 11 * bet.amount,
-                2 => 17 * bet.amount,
-                1 => 35 * bet.amount,
-                _ => 0,
-            };
+        let won = bet.kind.covered(variant).contains(&lucky_number);
+
+        let returned = if bet.imprisoned {
+            bet.imprisoned = false;
+            if won {
+                bet.amount
+            } else {
+                Money::ZERO
+            }
+        } else if won {
+            bet.amount.checked_mul(bet.kind.payout() + 1)?
+        } else if lucky_number == 0 && bet.kind.is_even_money() {
+            match variant {
+                Variant::French {
+                    rule: ZeroRule::LaPartage,
+                } => bet.amount.half(),
+                Variant::French {
+                    rule: ZeroRule::EnPrison,
+                } => {
+                    bet.imprisoned = true;
+                    bet.amount
+                }
+                Variant::European | Variant::American => Money::ZERO,
+            }
+        } else {
+            Money::ZERO
+        };
+
+        settlements.push(Settlement {
+            kind: bet.kind.clone(),
+            amount: bet.amount,
+            returned,
+        });
+    }
+
+    Ok(Judgement {
+        winning_amount: Money::checked_sum(settlements.iter().map(|s| s.returned))?,
+        bet_amount: Money::checked_sum(settlements.iter().map(|s| s.amount))?,
+        settlements,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VARIANTS: [Variant; 4] = [
+        Variant::European,
+        Variant::American,
+        Variant::French {
+            rule: ZeroRule::LaPartage,
+        },
+        Variant::French {
+            rule: ZeroRule::EnPrison,
+        },
+    ];
+    const STAKE: Money = Money::new(10);
+
+    /// Every bet `variant` takes.
+    fn legal_bets(variant: Variant) -> Vec<BetKind> {
+        let pockets = 0..=DOUBLE_ZERO;
+        let mut bets: Vec<BetKind> = pockets
+            .clone()
+            .map(|number| BetKind::Straight { number })
+            .collect();
+        for a in pockets.clone() {
+            for b in a + 1..=DOUBLE_ZERO {
+                bets.push(BetKind::Split { numbers: [a, b] });
+                for c in b + 1..=DOUBLE_ZERO {
+                    bets.push(BetKind::Trio { numbers: [a, b, c] });
+                }
+            }
+            bets.push(BetKind::Street { first: a });
+            bets.push(BetKind::Corner { first: a });
+            bets.push(BetKind::SixLine { first: a });
+            bets.push(BetKind::Column { column: a });
+            bets.push(BetKind::Dozen { dozen: a });
         }
+        bets.extend([
+            BetKind::TopLine,
+            BetKind::Basket,
+            BetKind::Red,
+            BetKind::Black,
+            BetKind::Even,
+            BetKind::Odd,
+            BetKind::Low,
+            BetKind::High,
+        ]);
+        bets.retain(|kind| kind.is_valid(variant));
+        bets
+    }
+
+    fn count(bets: &[BetKind], bet_type: BetType) -> usize {
+        bets.iter()
+            .filter(|kind| kind.bet_type() == bet_type)
+            .count()
     }
 
-    Judgement {
-        winning_amount,
-        bet_amount,
+    #[test]
+    fn legal_bets_are_the_board() {
+        for variant in VARIANTS {
+            let american = variant == Variant::American;
+            let bets = legal_bets(variant);
+            let expected = [
+                (BetType::Straight, variant.pockets() as usize),
+                (BetType::Split, if american { 62 } else { 60 }),
+                (BetType::Street, 12),
+                (BetType::Trio, if american { 3 } else { 2 }),
+                (BetType::Corner, 22),
+                (BetType::SixLine, 11),
+                (BetType::TopLine, usize::from(!american)),
+                (BetType::Basket, usize::from(american)),
+                (BetType::Column, 3),
+                (BetType::Dozen, 3),
+                (BetType::Red, 1),
+                (BetType::High, 1),
+            ];
+            for (bet_type, expected) in expected {
+                assert_eq!(
+                    count(&bets, bet_type),
+                    expected,
+                    "{:?} {:?}",
+                    variant,
+                    bet_type
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn payouts_match_coverage() {
+        for variant in VARIANTS {
+            for kind in legal_bets(variant) {
+                let mut covered = kind.covered(variant);
+                let pockets = covered.len();
+                covered.sort();
+                covered.dedup();
+                assert_eq!(covered.len(), pockets, "{:?} covers a pocket twice", kind);
+                assert!(covered.iter().all(|&pocket| pocket < variant.pockets()));
+
+                let returned = (kind.payout() + 1) * pockets as i64;
+                match kind {
+                    BetKind::Basket => assert_eq!(returned, 35),
+                    _ => assert_eq!(returned, 36, "{:?} on {:?}", kind, variant),
+                }
+            }
+        }
+    }
+
+    #[rocket::async_test]
+    async fn every_bet_settles_against_every_pocket() {
+        for variant in VARIANTS {
+            for kind in legal_bets(variant) {
+                let covered = kind.covered(variant);
+                for pocket in 0..variant.pockets() {
+                    let mut bets = [Bet {
+                        id: 1,
+                        kind: kind.clone(),
+                        amount: STAKE,
+                        imprisoned: false,
+                    }];
+                    let judgement = judge_player(&mut bets, pocket, variant).await.unwrap();
+
+                    let zero_rule = match variant {
+                        Variant::French { rule } if pocket == 0 && kind.is_even_money() => {
+                            Some(rule)
+                        }
+                        _ => None,
+                    };
+                    let expected = match zero_rule {
+                        _ if covered.contains(&pocket) => {
+                            STAKE.checked_mul(kind.payout() + 1).unwrap()
+                        }
+                        Some(ZeroRule::LaPartage) => Money::new(5),
+                        Some(ZeroRule::EnPrison) => STAKE,
+                        None => Money::ZERO,
+                    };
+                    assert_eq!(
+                        judgement.winning_amount, expected,
+                        "{:?} on {} at {:?}",
+                        kind, pocket, variant
+                    );
+                    assert_eq!(judgement.bet_amount, STAKE);
+                    assert_eq!(bets[0].imprisoned, zero_rule == Some(ZeroRule::EnPrison));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chips_on_zero() {
+        let straight = Some(BetKind::Straight { number: 0 });
+        let placements = [
+            (Placement::Center, (35, 105), straight.clone()),
+            (Placement::Left, (0, 105), straight.clone()),
+            (Placement::Top, (35, 0), straight.clone()),
+            (Placement::Bottom, (35, 210), straight.clone()),
+            (Placement::TopLeft, (0, 0), straight.clone()),
+            (Placement::TopRight, (70, 0), straight.clone()),
+            (Placement::BottomLeft, (0, 210), straight.clone()),
+            (Placement::BottomRight, (70, 210), straight),
+            (
+                Placement::Right,
+                (70, 10),
+                Some(BetKind::Split { numbers: [0, 1] }),
+            ),
+            (
+                Placement::Right,
+                (70, 105),
+                Some(BetKind::Split { numbers: [0, 2] }),
+            ),
+            (
+                Placement::Right,
+                (70, 210),
+                Some(BetKind::Split { numbers: [0, 3] }),
+            ),
+        ];
+        for (placement, local_position, expected) in placements {
+            assert_eq!(
+                BetKind::from_board("0", placement, local_position).ok(),
+                expected,
+                "{:?} at {:?}",
+                placement,
+                local_position
+            );
+        }
+    }
+
+    #[rocket::async_test]
+    async fn imprisoned_bets_come_back_without_winnings() {
+        let variant = Variant::French {
+            rule: ZeroRule::EnPrison,
+        };
+        for (pocket, returned) in [(2, STAKE), (1, Money::ZERO), (0, Money::ZERO)] {
+            let mut bets = [Bet {
+                id: 1,
+                kind: BetKind::Even,
+                amount: STAKE,
+                imprisoned: true,
+            }];
+            let judgement = judge_player(&mut bets, pocket, variant).await.unwrap();
+            assert_eq!(judgement.winning_amount, returned, "on {}", pocket);
+            assert!(!bets[0].imprisoned);
+        }
     }
 }
@@ -0,0 +1,230 @@
+use std::sync::Arc;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    judge::Settlement,
+    money::Money,
+    storage::{RoundId, Storage},
+    structs::{PlayerId, TableId, Timestamp},
+};
+
+pub(crate) type EntryId = i64;
+
+/// Somewhere money can sit. Every player has a wallet per table and each
+/// table's house account takes the other side of their bets.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Account {
+    Player {
+        table_id: TableId,
+        player_id: PlayerId,
+    },
+    House {
+        table_id: TableId,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub(crate) enum EntryKind {
+    Stake,
+    Win,
+    Refund,
+    TopUp,
+    Adjustment,
+}
+
+/// Moves `amount` from `debit` to `credit`. Entries are never changed once
+/// written and an entry whose `idempotency_key` was already posted is ignored.
+#[derive(Debug, Clone)]
+pub(crate) struct LedgerEntry {
+    pub(crate) idempotency_key: Arc<str>,
+    pub(crate) kind: EntryKind,
+    pub(crate) debit: Account,
+    pub(crate) credit: Account,
+    pub(crate) amount: Money,
+    pub(crate) timestamp: Timestamp,
+}
+
+/// A posted entry as seen from one account.
+#[derive(Debug, Clone)]
+pub(crate) struct PostedEntry {
+    pub(crate) id: EntryId,
+    pub(crate) kind: EntryKind,
+    /// Negative when money left the account.
+    pub(crate) amount: Money,
+    pub(crate) timestamp: Timestamp,
+}
+
+impl Account {
+    pub(crate) fn player(table_id: &TableId, player_id: &PlayerId) -> Self {
+        Account::Player {
+            table_id: table_id.clone(),
+            player_id: *player_id,
+        }
+    }
+
+    pub(crate) fn house(table_id: &TableId) -> Self {
+        Account::House {
+            table_id: table_id.clone(),
+        }
+    }
+}
+
+impl LedgerEntry {
+    pub(crate) fn new(
+        idempotency_key: impl Into<Arc<str>>,
+        kind: EntryKind,
+        debit: Account,
+        credit: Account,
+        amount: Money,
+    ) -> anyhow::Result<Self> {
+        if !amount.is_positive() {
+            anyhow::bail!("Ledger entries must move a positive amount, got {}", amount);
+        }
+        Ok(Self {
+            idempotency_key: idempotency_key.into(),
+            kind,
+            debit,
+            credit,
+            amount,
+            timestamp: chrono::offset::Utc::now().timestamp(),
+        })
+    }
+
+    /// Money the house gives a player for free, like the starting balance.
+    pub(crate) fn top_up(
+        idempotency_key: impl Into<Arc<str>>,
+        table_id: &TableId,
+        player_id: &PlayerId,
+        amount: Money,
+    ) -> anyhow::Result<Self> {
+        Self::new(
+            idempotency_key,
+            EntryKind::TopUp,
+            Account::house(table_id),
+            Account::player(table_id, player_id),
+            amount,
+        )
+    }
+}
+
+/// Identifies the settlement of a round by its row in `rounds`, so posting it
+/// again changes nothing.
+pub(crate) fn round_key(round_id: RoundId) -> String {
+    format!("round:{}", round_id)
+}
+
+/// Entries settling one player's bets in a round: a stake for every bet and a
+/// win or refund for whatever came back.
+pub(crate) fn round_entries(
+    round_key: &str,
+    table_id: &TableId,
+    player_id: &PlayerId,
+    settlements: &[Settlement],
+) -> anyhow::Result<Vec<LedgerEntry>> {
+    let player = Account::player(table_id, player_id);
+    let house = Account::house(table_id);
+
+    let mut entries = Vec::new();
+    for (index, settlement) in settlements.iter().enumerate() {
+        let key = format!("{}:{}:{}", round_key, player_id, index);
+        entries.push(LedgerEntry::new(
+            format!("{}:stake", key),
+            EntryKind::Stake,
+            player.clone(),
+            house.clone(),
+            settlement.amount,
+        )?);
+        if settlement.returned.is_positive() {
+            let kind = match settlement.returned > settlement.amount {
+                true => EntryKind::Win,
+                false => EntryKind::Refund,
+            };
+            entries.push(LedgerEntry::new(
+                format!("{}:return", key),
+                kind,
+                house.clone(),
+                player.clone(),
+                settlement.returned,
+            )?);
+        }
+    }
+    Ok(entries)
+}
+
+/// Checks in-memory balances against the ledger, which wins where they
+/// disagree. Every account is read in one go.
+pub(crate) fn reconcile(
+    storage: &dyn Storage,
+    balances: &mut [(Account, Money)],
+) -> anyhow::Result<()> {
+    let accounts: Vec<Account> = balances
+        .iter()
+        .map(|(account, _)| account.clone())
+        .collect();
+    let ledger_balances = storage.balances(&accounts)?;
+    for ((account, balance), ledger_balance) in balances.iter_mut().zip(ledger_balances) {
+        if ledger_balance != *balance {
+            log::error!(
+                "Balance of {:?} was {} but the ledger says {}",
+                account,
+                balance,
+                ledger_balance
+            );
+            *balance = ledger_balance;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{judge::BetKind, storage::SqliteStorage};
+
+    #[test]
+    fn posting_a_round_twice_settles_it_once() {
+        let storage = SqliteStorage::open(":memory:").unwrap();
+        let table_id: TableId = "ledger".to_owned();
+        let player_id = PlayerId::new_v4();
+        let settlements = [Settlement {
+            kind: BetKind::Red,
+            amount: Money::new(10),
+            returned: Money::new(20),
+        }];
+
+        let round = round_key(1);
+        for _ in 0..2 {
+            let entries = round_entries(&round, &table_id, &player_id, &settlements).unwrap();
+            storage.post(&entries).unwrap();
+        }
+        let player = Account::player(&table_id, &player_id);
+        assert_eq!(
+            storage.balances(std::slice::from_ref(&player)).unwrap()[0],
+            Money::new(10)
+        );
+
+        let next_round = round_key(2);
+        let entries = round_entries(&next_round, &table_id, &player_id, &settlements).unwrap();
+        storage.post(&entries).unwrap();
+        assert_eq!(storage.balances(&[player]).unwrap()[0], Money::new(20));
+    }
+
+    #[test]
+    fn reconcile_trusts_the_ledger() {
+        let storage = SqliteStorage::open(":memory:").unwrap();
+        let table_id: TableId = "reconcile".to_owned();
+        let (topped_up, untouched) = (PlayerId::new_v4(), PlayerId::new_v4());
+        let entry = LedgerEntry::top_up("welcome", &table_id, &topped_up, Money::new(50));
+        storage.post(&[entry.unwrap()]).unwrap();
+
+        let mut balances = [
+            (Account::player(&table_id, &topped_up), Money::new(70)),
+            (Account::player(&table_id, &untouched), Money::ZERO),
+        ];
+        reconcile(&storage, &mut balances).unwrap();
+        assert_eq!(balances[0].1, Money::new(50));
+        assert_eq!(balances[1].1, Money::ZERO);
+    }
+}
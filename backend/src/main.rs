@@ -1,53 +1,104 @@
 #[macro_use]
 extern crate rocket;
 
+pub(crate) mod bet_validator;
+pub(crate) mod encoding;
+pub(crate) mod event_log;
+pub(crate) mod fairness;
 pub(crate) mod helper;
+pub(crate) mod http_handler;
 pub(crate) mod judge;
+pub(crate) mod ledger;
+pub(crate) mod money;
+pub(crate) mod outcome_source;
+pub(crate) mod protocol;
+#[cfg(test)]
+mod protocol_types;
+pub(crate) mod racetrack;
+pub(crate) mod session;
 pub(crate) mod spin_timmer;
+pub(crate) mod statistics;
+pub(crate) mod storage;
 pub(crate) mod structs;
+pub(crate) mod table_actor;
+pub(crate) mod table_config;
 pub(crate) mod ws_messages;
 pub(crate) mod ws_messages_handler;
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
+use encoding::Encoding;
+use helper::Heartbeat;
+use outcome_source::OutcomeConfig;
 use rocket::{
+    fairing::AdHoc,
     futures::{SinkExt, StreamExt},
-    serde::json,
-    tokio::{select, sync::mpsc},
+    tokio::{
+        select,
+        sync::mpsc,
+        time::{self, Instant},
+    },
     State,
 };
 use rocket_ws::{self as ws, Message};
+use session::{Session, SessionKeys};
+use storage::SqliteStorage;
 use structs::Game;
 
 pub(crate) type ArcGame = Arc<structs::Game>;
 
-#[get("/game_ws")]
-async fn game_ws<'a>(ws: ws::WebSocket, tables: &State<ArcGame>) -> ws::Channel<'static> {
+const DEFAULT_DATABASE: &str = "roulette.sqlite";
+const DEFAULT_TABLE_IDLE_GRACE_SECS: u64 = 300;
+const DEFAULT_HEARTBEAT_INTERVAL_SECS: u64 = 15;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 60;
+
+#[get("/game_ws?<encoding>")]
+async fn game_ws(
+    ws: ws::WebSocket,
+    tables: &State<ArcGame>,
+    session: Session,
+    encoding: Option<Encoding>,
+    heartbeat: &State<Heartbeat>,
+) -> ws::Channel<'static> {
     let game: ArcGame = tables.inner().clone();
+    let encoding = encoding.unwrap_or_default();
+    let heartbeat = *heartbeat.inner();
 
     ws.channel(move |mut stream| {
         Box::pin(async move {
             let mut current_player_id: Option<structs::PlayerId> = None;
             let mut current_table_id: Option<structs::TableId> = None;
-            let (ws_channel_sender, mut ws_channel_receiver) = mpsc::channel::<ws_messages::ResponseMessages>(10);
+            let mut protocol = protocol::Protocol::default();
+            let (ws_channel_sender, mut ws_channel_receiver) = mpsc::channel::<ws_messages::OutboundMessage>(helper::CONNECTION_QUEUE_CAPACITY);
+            let mut pings = time::interval_at(Instant::now() + heartbeat.interval, heartbeat.interval);
+            let mut last_heard = Instant::now();
+            let mut last_ping: Option<Instant> = None;
+            let mut away = false;
             loop {
                 select! {
-                    Some(message) = stream.next() => {
+                    message = stream.next() => {
+                        let Some(message) = message else {
+                            break;
+                        };
                         match message {
                             Ok(message) => {
+                                // Pongs count too, they're how quiet clients show they're there.
+                                last_heard = Instant::now();
+                                if away {
+                                    away = false;
+                                    if let Err(e) = ws_messages_handler::set_away(game.clone(), ws_channel_sender.clone(), &current_player_id, &current_table_id, false).await {
+                                        log::error!("{:?}", e);
+                                    }
+                                }
                                 match message {
-                                    Message::Close(_) => { 
-                                        match ws_messages_handler::handle_close(game.clone(), &current_player_id, &current_table_id).await {
-                                            Ok(()) => {},
-                                            Err(e) => {
-                                                log::error!("{:?}", e);
-                                                continue;
-                                            }
-                                        }
+                                    Message::Close(_) => {
                                         break;
                                     }
-                                    _ => {
-                                        match ws_messages_handler::handle(message, game.clone(), ws_channel_sender.clone(), &mut current_player_id, &mut current_table_id).await {
+                                    message => {
+                                        let Some(message) = encoding.read(message) else {
+                                            continue;
+                                        };
+                                        match ws_messages_handler::handle(message, game.clone(), ws_channel_sender.clone(), &session, &mut protocol, &mut current_player_id, &mut current_table_id).await {
                                             Ok(()) => {},
                                             Err(e) => {
                                                 log::error!("{:?}", e);
@@ -64,7 +115,10 @@ async fn game_ws<'a>(ws: ws::WebSocket, tables: &State<ArcGame>) -> ws::Channel<
                         }
                     },
                     Some(message) = ws_channel_receiver.recv() => {
-                        let message_as_json = match json::to_string(&message) {
+                        let encoded = protocol.encode(&message)
+                            .map_err(anyhow::Error::from)
+                            .and_then(|message| encoding.write(&message));
+                        let message = match encoded {
                             Ok(m) => m,
                             Err(e) => {
                                 log::error!("{:?}", e);
@@ -72,16 +126,50 @@ async fn game_ws<'a>(ws: ws::WebSocket, tables: &State<ArcGame>) -> ws::Channel<
                             }
                         };
 
-                        match stream.send(Message::Text(message_as_json)).await {
-                            Ok(())=> {},
-                            Err(e) => {
+                        // A client that stops reading would otherwise hold the
+                        // connection open past the idle timeout.
+                        match time::timeout(heartbeat.idle_timeout, stream.send(message)).await {
+                            Ok(Ok(())) => {},
+                            Ok(Err(e)) => {
                                 log::error!("{:?}", e);
                                 continue;
                             }
+                            Err(_) => {
+                                log::info!("Dropping a connection that stopped reading");
+                                break;
+                            }
                         };
                     }
+                    _ = pings.tick() => {
+                        let quiet = last_heard.elapsed();
+                        if quiet >= heartbeat.idle_timeout {
+                            log::info!("Dropping a connection quiet for {:?}", quiet);
+                            break;
+                        }
+                        if !away && last_ping.is_some_and(|last_ping| last_heard < last_ping) {
+                            away = true;
+                            if let Err(e) = ws_messages_handler::set_away(game.clone(), ws_channel_sender.clone(), &current_player_id, &current_table_id, true).await {
+                                log::error!("{:?}", e);
+                            }
+                        }
+                        match time::timeout(heartbeat.idle_timeout, stream.send(Message::Ping(Vec::new()))).await {
+                            Ok(Ok(())) => {},
+                            Ok(Err(e)) => {
+                                log::error!("{:?}", e);
+                                break;
+                            }
+                            Err(_) => {
+                                log::info!("Dropping a connection that stopped reading");
+                                break;
+                            }
+                        }
+                        last_ping = Some(Instant::now());
+                    }
                 }
             }
+            if let Err(e) = ws_messages_handler::handle_close(game.clone(), ws_channel_sender, &current_player_id, &current_table_id).await {
+                log::error!("{:?}", e);
+            }
             Ok(())
         })
     })
@@ -89,6 +177,84 @@ async fn game_ws<'a>(ws: ws::WebSocket, tables: &State<ArcGame>) -> ws::Channel<
 
 #[launch]
 fn launch() -> _ {
-    let game: ArcGame = Arc::new(Game::default());
-    rocket::build().manage(game).mount("/", routes![game_ws])
+    let rocket = rocket::build();
+    let outcome_config: OutcomeConfig = match rocket.figment().extract_inner("outcome_source") {
+        Ok(outcome_config) => outcome_config,
+        Err(e) if e.missing() => OutcomeConfig::default(),
+        Err(e) => panic!("Invalid outcome_source: {}", e),
+    };
+    if let Err(e) = outcome_config.validate() {
+        panic!("Invalid outcome_source: {}", e);
+    }
+
+    let database: String = match rocket.figment().extract_inner("database") {
+        Ok(database) => database,
+        Err(e) if e.missing() => DEFAULT_DATABASE.to_owned(),
+        Err(e) => panic!("Invalid database: {}", e),
+    };
+    let storage = match SqliteStorage::open(&database) {
+        Ok(storage) => Arc::new(storage),
+        Err(e) => panic!("Can't open database {}: {}", database, e),
+    };
+
+    let session_keys = match rocket.figment().extract_inner::<String>("session_secret") {
+        Ok(secret) => SessionKeys::new(secret),
+        Err(e) if e.missing() => {
+            log::warn!("No session_secret configured, sessions won't survive a restart");
+            SessionKeys::random()
+        }
+        Err(e) => panic!("Invalid session_secret: {}", e),
+    };
+
+    let table_idle_grace: u64 = match rocket.figment().extract_inner("table_idle_grace_secs") {
+        Ok(table_idle_grace) => table_idle_grace,
+        Err(e) if e.missing() => DEFAULT_TABLE_IDLE_GRACE_SECS,
+        Err(e) => panic!("Invalid table_idle_grace_secs: {}", e),
+    };
+
+    let heartbeat_interval: u64 = match rocket.figment().extract_inner("heartbeat_interval_secs") {
+        Ok(heartbeat_interval) => heartbeat_interval,
+        Err(e) if e.missing() => DEFAULT_HEARTBEAT_INTERVAL_SECS,
+        Err(e) => panic!("Invalid heartbeat_interval_secs: {}", e),
+    };
+    let idle_timeout: u64 = match rocket.figment().extract_inner("idle_timeout_secs") {
+        Ok(idle_timeout) => idle_timeout,
+        Err(e) if e.missing() => DEFAULT_IDLE_TIMEOUT_SECS,
+        Err(e) => panic!("Invalid idle_timeout_secs: {}", e),
+    };
+    if heartbeat_interval == 0 || idle_timeout <= heartbeat_interval {
+        panic!("idle_timeout_secs has to be longer than a non zero heartbeat_interval_secs");
+    }
+    let heartbeat = Heartbeat {
+        interval: Duration::from_secs(heartbeat_interval),
+        idle_timeout: Duration::from_secs(idle_timeout),
+    };
+
+    let game: ArcGame = Arc::new(Game::new(
+        outcome_config,
+        storage,
+        Duration::from_secs(table_idle_grace),
+    ));
+    rocket
+        .manage(game)
+        .manage(session_keys)
+        .manage(heartbeat)
+        .attach(AdHoc::on_shutdown("Close tables", |rocket| {
+            Box::pin(async move {
+                if let Some(game) = rocket.state::<ArcGame>() {
+                    game.close_tables().await;
+                }
+            })
+        }))
+        .mount(
+            "/",
+            routes![
+                game_ws,
+                http_handler::verify_spin,
+                http_handler::guest_session,
+                http_handler::refresh_session,
+                http_handler::table_statistics,
+                http_handler::table_history
+            ],
+        )
 }
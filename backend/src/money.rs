@@ -0,0 +1,94 @@
+use std::fmt::Display;
+
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Whole currency units. Arithmetic is checked so a balance can never wrap.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+#[serde(transparent)]
+pub(crate) struct Money(i64);
+
+impl Money {
+    pub(crate) const ZERO: Money = Money(0);
+
+    pub(crate) const fn new(amount: i64) -> Self {
+        Self(amount)
+    }
+
+    pub(crate) fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+
+    pub(crate) fn checked_add(self, other: Money) -> anyhow::Result<Money> {
+        self.0
+            .checked_add(other.0)
+            .map(Money)
+            .ok_or_else(|| anyhow::anyhow!("{} + {} overflows", self, other))
+    }
+
+    pub(crate) fn checked_sub(self, other: Money) -> anyhow::Result<Money> {
+        self.0
+            .checked_sub(other.0)
+            .map(Money)
+            .ok_or_else(|| anyhow::anyhow!("{} - {} overflows", self, other))
+    }
+
+    pub(crate) fn checked_mul(self, factor: i64) -> anyhow::Result<Money> {
+        self.0
+            .checked_mul(factor)
+            .map(Money)
+            .ok_or_else(|| anyhow::anyhow!("{} * {} overflows", self, factor))
+    }
+
+    /// Rounds towards zero.
+    pub(crate) fn half(self) -> Money {
+        Money(self.0 / 2)
+    }
+
+    pub(crate) fn checked_sum(amounts: impl IntoIterator<Item = Money>) -> anyhow::Result<Money> {
+        amounts
+            .into_iter()
+            .try_fold(Money::ZERO, |total, amount| total.checked_add(amount))
+    }
+
+    /// For totals that are only displayed.
+    pub(crate) fn saturating_sum(amounts: impl IntoIterator<Item = Money>) -> Money {
+        Money(
+            amounts
+                .into_iter()
+                .fold(0i64, |total, amount| total.saturating_add(amount.0)),
+        )
+    }
+}
+
+impl Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl ToSql for Money {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        self.0.to_sql()
+    }
+}
+
+impl FromSql for Money {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        i64::column_result(value).map(Money)
+    }
+}
@@ -0,0 +1,229 @@
+use std::{fmt::Debug, sync::Arc};
+
+use rand::{rngs::OsRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::Deserialize;
+
+use crate::{
+    fairness::{derive_pocket, FairRound, ServerSeed},
+    judge::{Pocket, Variant},
+};
+
+/// Decides where the ball lands. Each table owns its own source.
+pub(crate) trait OutcomeSource: Send + Debug {
+    fn server_seed(&mut self) -> ServerSeed;
+
+    /// Returns a pocket in `0..pockets`.
+    fn next_pocket(
+        &mut self,
+        pockets: u32,
+        round: &FairRound,
+        client_seeds: &[Arc<str>],
+    ) -> Pocket {
+        derive_pocket(round.server_seed(), client_seeds, round.nonce(), pockets)
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct OsOutcome;
+
+#[derive(Debug)]
+pub(crate) struct SeededOutcome {
+    rng: ChaCha20Rng,
+}
+
+#[derive(Debug)]
+pub(crate) struct ScriptedOutcome {
+    pockets: Vec<Pocket>,
+    position: usize,
+}
+
+/// Which `OutcomeSource` new tables get, read from the `outcome_source` config key.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OutcomeConfig {
+    #[default]
+    Os,
+    /// Deals the same server seeds every time a table opens, including when it
+    /// reopens after sitting idle, so anyone knowing `seed` can tell where the
+    /// ball lands. For tests only.
+    Seeded {
+        seed: u64,
+    },
+    Scripted {
+        pockets: Vec<Pocket>,
+    },
+}
+
+impl OutcomeSource for OsOutcome {
+    fn server_seed(&mut self) -> ServerSeed {
+        let mut server_seed = ServerSeed::default();
+        OsRng.fill_bytes(&mut server_seed);
+        server_seed
+    }
+}
+
+impl SeededOutcome {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
+            rng: ChaCha20Rng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl OutcomeSource for SeededOutcome {
+    fn server_seed(&mut self) -> ServerSeed {
+        let mut server_seed = ServerSeed::default();
+        self.rng.fill_bytes(&mut server_seed);
+        server_seed
+    }
+}
+
+impl ScriptedOutcome {
+    pub(crate) fn new(pockets: Vec<Pocket>) -> Self {
+        Self {
+            pockets,
+            position: 0,
+        }
+    }
+}
+
+impl OutcomeSource for ScriptedOutcome {
+    fn server_seed(&mut self) -> ServerSeed {
+        ServerSeed::default()
+    }
+
+    /// Replays the script in order, starting over once it runs out. The
+    /// published proofs won't verify.
+    fn next_pocket(&mut self, pockets: u32, _: &FairRound, _: &[Arc<str>]) -> Pocket {
+        let pocket = self.pockets[self.position % self.pockets.len()];
+        self.position += 1;
+        pocket % pockets
+    }
+}
+
+impl OutcomeConfig {
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        if let OutcomeConfig::Scripted { pockets } = self {
+            if pockets.is_empty() {
+                anyhow::bail!("Scripted outcome source needs at least one pocket");
+            }
+            let most_pockets = Variant::American.pockets();
+            if let Some(pocket) = pockets.iter().find(|&&p| p >= most_pockets) {
+                anyhow::bail!("Scripted pocket {} is not on the wheel", pocket);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn build(&self) -> Box<dyn OutcomeSource> {
+        match self {
+            OutcomeConfig::Os => Box::new(OsOutcome),
+            OutcomeConfig::Seeded { seed } => Box::new(SeededOutcome::new(*seed)),
+            OutcomeConfig::Scripted { pockets } => Box::new(ScriptedOutcome::new(pockets.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rocket::{
+        serde::json::json,
+        tokio::{sync::mpsc, time},
+    };
+
+    use super::*;
+    use crate::{
+        helper::CONNECTION_QUEUE_CAPACITY,
+        ledger::Account,
+        money::Money,
+        protocol::Protocol,
+        session::SessionKeys,
+        storage::{SqliteStorage, Storage},
+        structs::{Game, DEFAULT_BALANCE},
+        ws_messages::ResponseMessages,
+        ws_messages_handler, ArcGame,
+    };
+
+    #[test]
+    fn scripted_outcome_replays_its_pockets() {
+        let mut source = ScriptedOutcome::new(vec![17, 0, 37]);
+        let round = FairRound::new(source.server_seed(), 0);
+        let pockets: Vec<Pocket> = (0..4)
+            .map(|_| source.next_pocket(Variant::American.pockets(), &round, &[]))
+            .collect();
+        assert_eq!(pockets, [17, 0, 37, 17]);
+    }
+
+    #[test]
+    fn seeded_outcome_repeats_for_a_seed() {
+        let seeds = |seed| {
+            let mut source = SeededOutcome::new(seed);
+            [source.server_seed(), source.server_seed()]
+        };
+        assert_eq!(seeds(7), seeds(7));
+        assert_ne!(seeds(7), seeds(8));
+    }
+
+    #[rocket::async_test]
+    async fn scripted_round_settles_over_the_websocket_handler() {
+        let storage = Arc::new(SqliteStorage::open(":memory:").unwrap());
+        let game: ArcGame = Arc::new(Game::new(
+            OutcomeConfig::Scripted { pockets: vec![17] },
+            storage.clone(),
+            Duration::from_secs(60),
+        ));
+        let keys = SessionKeys::random();
+        let session = keys.verify(&keys.guest().token).unwrap();
+        let (sender, mut receiver) = mpsc::channel(CONNECTION_QUEUE_CAPACITY);
+        let mut protocol = Protocol::default();
+        let (mut player_id, mut table_id) = (None, None);
+
+        for request in [
+            json!({"JoinTable": {"table_id": "scripted", "name": "ann"}}),
+            json!({"AddBet": {"kind": {"Straight": {"number": 17}}, "amount": 10}}),
+            json!({"AddBet": {"kind": "Red", "amount": 20}}),
+            json!("RequestSpin"),
+        ] {
+            ws_messages_handler::handle(
+                Ok(request),
+                game.clone(),
+                sender.clone(),
+                &session,
+                &mut protocol,
+                &mut player_id,
+                &mut table_id,
+            )
+            .await
+            .unwrap();
+        }
+
+        let spin = time::timeout(Duration::from_secs(5), async {
+            while let Some(message) = receiver.recv().await {
+                if let ResponseMessages::Spin {
+                    lucky_number,
+                    winning_amount,
+                    balance,
+                    ..
+                } = message.message
+                {
+                    return (lucky_number, winning_amount, balance);
+                }
+            }
+            panic!("Connection closed before the spin");
+        })
+        .await
+        .expect("No spin came");
+
+        // 17 is black: the straight pays 35 to 1 and red loses.
+        let expected = DEFAULT_BALANCE
+            .checked_sub(Money::new(30))
+            .and_then(|balance| balance.checked_add(Money::new(360)))
+            .unwrap();
+        assert_eq!(spin, (17, Money::new(360), expected));
+        let account = Account::player(&"scripted".to_owned(), &session.player_id);
+        assert_eq!(storage.balances(&[account]).unwrap()[0], expected);
+    }
+}
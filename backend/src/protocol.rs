@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use rocket::serde::json::{self, serde_json, Value};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event_log::Seq,
+    ws_messages::{OutboundMessage, RequestMessages},
+};
+
+pub(crate) const OLDEST_PROTOCOL_VERSION: u32 = 1;
+pub(crate) const LATEST_PROTOCOL_VERSION: u32 = 2;
+
+/// Picked by the client to match replies with the requests they answer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub(crate) enum RequestId {
+    Number(u64),
+    Text(Arc<str>),
+}
+
+/// How messages are written on a connection. Connections speak `V1` until the
+/// client says `Hello` with another version, which is only taken before
+/// joining a table. `Hello` can be written the way the version asked for is.
+///
+/// A version is never changed once released: adding a message or an optional
+/// field is fine, anything a client could trip over (renaming or removing a
+/// message or field, changing what a field means) needs a new version, and the
+/// old one keeps being served.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Protocol {
+    /// `{"AddBet":{..}}`, table events with their `seq` next to the message,
+    /// no request ids.
+    #[default]
+    V1,
+    /// `{"type":"AddBet","id":..,"payload":{..}}`. Replies echo the `id` of
+    /// the request, table events carry a `seq` instead. `payload` is left out
+    /// of messages without fields.
+    V2,
+}
+
+/// A `V2` request.
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct InboundEnvelope {
+    /// Name of the `RequestMessages` variant.
+    #[serde(rename = "type")]
+    kind: String,
+    id: Option<RequestId>,
+    /// Fields of the request, left out when it has none.
+    payload: Option<Value>,
+}
+
+/// A `V2` reply or table event.
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct OutboundEnvelope<'a> {
+    /// Name of the `ResponseMessages` variant.
+    #[serde(rename = "type")]
+    kind: &'a str,
+    /// The `id` of the request replied to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<&'a RequestId>,
+    /// Set on table events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seq: Option<Seq>,
+    /// Fields of the message, left out when it has none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<&'a Value>,
+}
+
+impl Protocol {
+    pub(crate) fn from_version(version: u32) -> Option<Self> {
+        match version {
+            1 => Some(Protocol::V1),
+            2 => Some(Protocol::V2),
+            _ => None,
+        }
+    }
+
+    /// Reads a request, along with its id when the envelope could be read.
+    pub(crate) fn decode(
+        &self,
+        value: Value,
+    ) -> (Option<RequestId>, serde_json::Result<RequestMessages>) {
+        match self {
+            Protocol::V1 => match RequestMessages::deserialize(&value) {
+                // Clients going for a later version can say Hello in it.
+                Err(e) => match Protocol::V2.decode(value) {
+                    (request_id, Ok(hello @ RequestMessages::Hello { .. })) => {
+                        (request_id, Ok(hello))
+                    }
+                    _ => (None, Err(e)),
+                },
+                request => (None, request),
+            },
+            Protocol::V2 => {
+                let envelope = match InboundEnvelope::deserialize(value) {
+                    Ok(envelope) => envelope,
+                    Err(e) => return (None, Err(e)),
+                };
+                let request = match envelope.payload {
+                    Some(Value::Null) | None => {
+                        // Requests whose fields are all optional can go
+                        // without a payload too.
+                        let empty = Value::Object(
+                            [(envelope.kind.clone(), Value::Object(Default::default()))]
+                                .into_iter()
+                                .collect(),
+                        );
+                        json::from_value(Value::String(envelope.kind))
+                            .or_else(|_| json::from_value(empty))
+                    }
+                    Some(payload) => json::from_value(Value::Object(
+                        [(envelope.kind, payload)].into_iter().collect(),
+                    )),
+                };
+                (envelope.id, request)
+            }
+        }
+    }
+
+    pub(crate) fn encode(&self, message: &OutboundMessage) -> serde_json::Result<Value> {
+        match self {
+            Protocol::V1 => json::to_value(message),
+            Protocol::V2 => {
+                let (kind, payload) = match json::to_value(&message.message)? {
+                    Value::String(kind) => (kind, None),
+                    Value::Object(object) => object
+                        .into_iter()
+                        .next()
+                        .map(|(kind, payload)| (kind, Some(payload)))
+                        .expect("messages are externally tagged"),
+                    _ => unreachable!("messages are externally tagged"),
+                };
+                json::to_value(OutboundEnvelope {
+                    kind: &kind,
+                    id: message.request_id.as_ref(),
+                    seq: message.seq,
+                    payload: payload.as_ref(),
+                })
+            }
+        }
+    }
+}
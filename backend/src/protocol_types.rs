@@ -0,0 +1,250 @@
+use std::{fmt::Write, fs};
+
+use rocket::serde::json::{self, json, Value};
+use schemars::gen::SchemaSettings;
+
+use crate::{
+    judge::{pocket_color, DOUBLE_ZERO},
+    protocol::{
+        InboundEnvelope, OutboundEnvelope, LATEST_PROTOCOL_VERSION, OLDEST_PROTOCOL_VERSION,
+    },
+    ws_messages::{RequestMessages, ResponseMessages, SequencedMessage},
+};
+
+const SCHEMA_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/protocol.schema.json");
+const TYPESCRIPT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../frontend/src/protocol.ts");
+
+/// The JSON Schema and TypeScript types of the websocket messages, checked in
+/// next to the code using them. `checked_in_types_match` fails once they no
+/// longer match the Rust types and `write_protocol_types` updates them.
+fn generated() -> [(&'static str, String); 2] {
+    let schema = schema();
+    let schema_json = json::to_pretty_string(&schema).expect("the schema is JSON") + "\n";
+    [
+        (SCHEMA_PATH, schema_json),
+        (TYPESCRIPT_PATH, typescript(&schema)),
+    ]
+}
+
+#[test]
+fn checked_in_types_match() {
+    for (path, generated) in generated() {
+        let checked_in = fs::read_to_string(path).unwrap_or_default();
+        assert!(
+            checked_in == generated,
+            "{} is out of date, run `cargo test -- --ignored write_protocol_types`",
+            path
+        );
+    }
+}
+
+#[test]
+#[ignore = "writes the checked in files"]
+fn write_protocol_types() {
+    for (path, generated) in generated() {
+        fs::write(path, generated).unwrap();
+    }
+}
+
+/// Every type that goes over the websocket, under `definitions`.
+/// `RequestMessages` and `ResponseMessages` are where they start, sent as is
+/// or in a `SequencedMessage` on `V1` and in an `InboundEnvelope` or
+/// `OutboundEnvelope` on `V2`.
+pub(crate) fn schema() -> Value {
+    let mut generator = SchemaSettings::draft07().into_generator();
+    let messages = [
+        generator.subschema_for::<RequestMessages>(),
+        generator.subschema_for::<ResponseMessages>(),
+        generator.subschema_for::<SequencedMessage>(),
+        generator.subschema_for::<InboundEnvelope>(),
+        generator.subschema_for::<OutboundEnvelope>(),
+    ];
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Roulette websocket protocol",
+        "anyOf": messages,
+        "definitions": generator.definitions(),
+    })
+}
+
+/// TypeScript declarations for the `definitions` of `schema`, along with the
+/// constants the frontend would otherwise have to copy.
+pub(crate) fn typescript(schema: &Value) -> String {
+    let mut out =
+        String::from("// Generated by `cargo test -- --ignored write_protocol_types` in backend/. Do not edit.\n\n");
+    let _ = writeln!(
+        out,
+        "export const OLDEST_PROTOCOL_VERSION = {};",
+        OLDEST_PROTOCOL_VERSION
+    );
+    let _ = writeln!(
+        out,
+        "export const LATEST_PROTOCOL_VERSION = {};\n",
+        LATEST_PROTOCOL_VERSION
+    );
+    let colors: Vec<String> = (0..=DOUBLE_ZERO)
+        .map(|pocket| json::to_string(&pocket_color(pocket)).unwrap_or_default())
+        .collect();
+    let _ = writeln!(
+        out,
+        "/** Color of every pocket by number, 37 being 00. */\nexport const POCKET_COLORS: PocketColor[] = [{}];",
+        colors.join(", ")
+    );
+
+    let definitions = schema["definitions"]
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+    for (name, definition) in &definitions {
+        out.push('\n');
+        write_doc(&mut out, definition, 0);
+        let _ = writeln!(
+            out,
+            "export type {} ={};",
+            name,
+            after_space(ts_type(definition, 0))
+        );
+    }
+    out
+}
+
+fn write_doc(out: &mut String, schema: &Value, indent: usize) {
+    let Some(description) = schema["description"].as_str() else {
+        return;
+    };
+    let pad = "  ".repeat(indent);
+    let _ = writeln!(out, "{}/**", pad);
+    for line in description.lines() {
+        let _ = writeln!(out, "{} * {}", pad, line);
+    }
+    let _ = writeln!(out, "{} */", pad);
+}
+
+fn ts_type(schema: &Value, indent: usize) -> String {
+    if let Some(reference) = schema["$ref"].as_str() {
+        return reference.trim_start_matches("#/definitions/").to_owned();
+    }
+    for key in ["anyOf", "oneOf"] {
+        if let Some(schemas) = schema[key].as_array() {
+            return union(schemas.iter(), indent);
+        }
+    }
+    if let Some(schemas) = schema["allOf"].as_array() {
+        return schemas
+            .iter()
+            .map(|schema| match ts_type(schema, indent) {
+                ts_type if schemas.len() > 1 && ts_type.contains('|') => format!("({})", ts_type),
+                ts_type => ts_type,
+            })
+            .collect::<Vec<_>>()
+            .join(" & ");
+    }
+    if let Some(values) = schema["enum"].as_array() {
+        return values
+            .iter()
+            .map(Value::to_string)
+            .collect::<Vec<_>>()
+            .join(" | ");
+    }
+    if let Some(value) = schema.get("const") {
+        return value.to_string();
+    }
+    match &schema["type"] {
+        Value::String(instance_type) => instance_ts_type(instance_type, schema, indent),
+        Value::Array(instance_types) => instance_types
+            .iter()
+            .filter_map(Value::as_str)
+            .map(|instance_type| instance_ts_type(instance_type, schema, indent))
+            .collect::<Vec<_>>()
+            .join(" | "),
+        _ => "unknown".to_owned(),
+    }
+}
+
+fn instance_ts_type(instance_type: &str, schema: &Value, indent: usize) -> String {
+    match instance_type {
+        "null" => "null".to_owned(),
+        "boolean" => "boolean".to_owned(),
+        "integer" | "number" => "number".to_owned(),
+        "string" => "string".to_owned(),
+        "array" => match &schema["items"] {
+            Value::Array(items) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|item| ts_type(item, indent))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Value::Object(_) => {
+                let item = ts_type(&schema["items"], indent);
+                match item.contains(" | ") {
+                    true => format!("({})[]", item),
+                    false => format!("{}[]", item),
+                }
+            }
+            _ => "unknown[]".to_owned(),
+        },
+        "object" => object_ts_type(schema, indent),
+        _ => "unknown".to_owned(),
+    }
+}
+
+fn object_ts_type(schema: &Value, indent: usize) -> String {
+    let Some(properties) = schema["properties"].as_object() else {
+        return match &schema["additionalProperties"] {
+            Value::Object(values) => format!(
+                "{{ [key: string]: {} }}",
+                ts_type(&Value::Object(values.clone()), indent)
+            ),
+            _ => "{ [key: string]: unknown }".to_owned(),
+        };
+    };
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    let mut out = String::from("{\n");
+    for (name, property) in properties {
+        write_doc(&mut out, property, indent + 1);
+        let optional = match required.contains(&name.as_str()) {
+            true => "",
+            false => "?",
+        };
+        let _ = writeln!(
+            out,
+            "{}{}{}:{};",
+            "  ".repeat(indent + 1),
+            name,
+            optional,
+            after_space(ts_type(property, indent + 1))
+        );
+    }
+    out.push_str(&"  ".repeat(indent));
+    out.push('}');
+    out
+}
+
+/// `ts_type` as it follows a `=` or `:`.
+fn after_space(ts_type: String) -> String {
+    match ts_type.starts_with('\n') {
+        true => ts_type,
+        false => format!(" {}", ts_type),
+    }
+}
+
+/// Alternatives spanning several lines go one per line.
+fn union<'a>(schemas: impl Iterator<Item = &'a Value>, indent: usize) -> String {
+    let types: Vec<String> = schemas.map(|schema| ts_type(schema, indent + 1)).collect();
+    match types.iter().any(|ts_type| ts_type.contains('\n')) {
+        true => {
+            let pad = "  ".repeat(indent + 1);
+            types
+                .iter()
+                .map(|ts_type| format!("\n{}| {}", pad, ts_type))
+                .collect()
+        }
+        false => types.join(" | "),
+    }
+}
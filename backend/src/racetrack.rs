@@ -0,0 +1,384 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    bet_validator::{BetRejection, RejectReason},
+    judge::{BetKind, Pocket, Variant},
+};
+
+/// Pockets in the order they sit around a single zero wheel.
+const WHEEL_ORDER: [Pocket; 37] = [
+    0, 32, 15, 19, 4, 21, 2, 25, 17, 34, 6, 27, 13, 36, 11, 30, 8, 23, 10, 5, 24, 16, 33, 1, 20,
+    14, 31, 9, 22, 18, 29, 7, 28, 12, 35, 3, 26,
+];
+
+const DEFAULT_NEIGHBOURS: u32 = 2;
+const MAX_NEIGHBOURS: u32 = 9;
+
+/// Bets called by name on the racetrack of single zero tables. Each is placed
+/// as the board bets it stands for.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub(crate) enum AnnouncedBet {
+    /// The 17 numbers between 22 and 25 around the zero, 9 chips.
+    VoisinsDuZero,
+    /// The 12 numbers opposite the zero, 6 chips.
+    TiersDuCylindre,
+    /// The 8 numbers left out by Voisins and Tiers, 5 chips.
+    Orphelins,
+    /// The 7 numbers closest to the zero, 4 chips.
+    JeuZero,
+    /// `number` and `neighbours` pockets on either side of it, a chip each.
+    Neighbours {
+        number: Pocket,
+        #[serde(default = "default_neighbours")]
+        neighbours: u32,
+    },
+    /// Every number ending in `digit`, a chip each.
+    Finale { digit: u32 },
+    /// Every number ending in either of `digits`, a chip on each split they
+    /// make and a chip on each number left over.
+    FinaleACheval { digits: [u32; 2] },
+}
+
+fn default_neighbours() -> u32 {
+    DEFAULT_NEIGHBOURS
+}
+
+impl AnnouncedBet {
+    /// The board bets this stands for, with how many chips go on each.
+    pub(crate) fn expand(&self, variant: Variant) -> Result<Vec<(BetKind, i64)>, BetRejection> {
+        if variant == Variant::American {
+            return Err(BetRejection::new(
+                RejectReason::InvalidBet,
+                format!("{:?} is not a bet on {:?} tables", self, variant),
+            ));
+        }
+
+        let bets = match self {
+            AnnouncedBet::VoisinsDuZero => vec![
+                (trio(0, 2, 3), 2),
+                (split(4, 7), 1),
+                (split(12, 15), 1),
+                (split(18, 21), 1),
+                (split(19, 22), 1),
+                (BetKind::Corner { first: 25 }, 2),
+                (split(32, 35), 1),
+            ],
+            AnnouncedBet::TiersDuCylindre => vec![
+                (split(5, 8), 1),
+                (split(10, 11), 1),
+                (split(13, 16), 1),
+                (split(23, 24), 1),
+                (split(27, 30), 1),
+                (split(33, 36), 1),
+            ],
+            AnnouncedBet::Orphelins => vec![
+                (straight(1), 1),
+                (split(6, 9), 1),
+                (split(14, 17), 1),
+                (split(17, 20), 1),
+                (split(31, 34), 1),
+            ],
+            AnnouncedBet::JeuZero => vec![
+                (split(0, 3), 1),
+                (split(12, 15), 1),
+                (straight(26), 1),
+                (split(32, 35), 1),
+            ],
+            AnnouncedBet::Neighbours { number, neighbours } => {
+                let Some(position) = WHEEL_ORDER.iter().position(|pocket| pocket == number) else {
+                    return Err(invalid(format!("{} is not on the wheel", number)));
+                };
+                if !(1..=MAX_NEIGHBOURS).contains(neighbours) {
+                    return Err(invalid(format!(
+                        "Neighbours must be between 1 and {}",
+                        MAX_NEIGHBOURS
+                    )));
+                }
+                let wheel = WHEEL_ORDER.len() as i64;
+                let neighbours = *neighbours as i64;
+                (-neighbours..=neighbours)
+                    .map(|offset| {
+                        let index = (position as i64 + offset).rem_euclid(wheel);
+                        (straight(WHEEL_ORDER[index as usize]), 1)
+                    })
+                    .collect()
+            }
+            AnnouncedBet::Finale { digit } => {
+                if *digit > 9 {
+                    return Err(invalid(format!("{} is not a digit", digit)));
+                }
+                (*digit..=36)
+                    .step_by(10)
+                    .map(|number| (straight(number), 1))
+                    .collect()
+            }
+            AnnouncedBet::FinaleACheval { digits: [a, b] } => {
+                if a == b || *a.max(b) > 9 {
+                    return Err(invalid(format!("{} and {} are not two digits", a, b)));
+                }
+                let (low, high) = (*a.min(b), *a.max(b));
+                (0..=30)
+                    .step_by(10)
+                    .flat_map(|tens| {
+                        let (low, high) = (tens + low, tens + high);
+                        match split(low, high) {
+                            split if split.is_valid(variant) => vec![(split, 1)],
+                            _ => [low, high]
+                                .into_iter()
+                                .filter(|&number| number <= 36)
+                                .map(|number| (straight(number), 1))
+                                .collect(),
+                        }
+                    })
+                    .collect()
+            }
+        };
+        Ok(bets)
+    }
+
+    /// Pockets that win some part of this bet.
+    pub(crate) fn covered(&self, variant: Variant) -> Vec<Pocket> {
+        let mut covered: Vec<Pocket> = self
+            .expand(variant)
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|(kind, _)| kind.covered(variant))
+            .collect();
+        covered.sort();
+        covered.dedup();
+        covered
+    }
+}
+
+fn invalid(detail: String) -> BetRejection {
+    BetRejection::new(RejectReason::InvalidBet, detail)
+}
+
+fn straight(number: Pocket) -> BetKind {
+    BetKind::Straight { number }
+}
+
+fn split(a: Pocket, b: Pocket) -> BetKind {
+    BetKind::Split { numbers: [a, b] }
+}
+
+fn trio(a: Pocket, b: Pocket, c: Pocket) -> BetKind {
+    BetKind::Trio { numbers: [a, b, c] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judge::ZeroRule;
+
+    const EUROPEAN: Variant = Variant::European;
+
+    fn chips(bet: &AnnouncedBet) -> i64 {
+        bet.expand(EUROPEAN)
+            .unwrap()
+            .iter()
+            .map(|(_, chips)| chips)
+            .sum()
+    }
+
+    fn sorted(mut numbers: Vec<Pocket>) -> Vec<Pocket> {
+        numbers.sort();
+        numbers
+    }
+
+    fn neighbours(number: Pocket, neighbours: u32) -> AnnouncedBet {
+        AnnouncedBet::Neighbours { number, neighbours }
+    }
+
+    #[test]
+    fn expands_into_valid_bets() {
+        let mut bets = vec![
+            AnnouncedBet::VoisinsDuZero,
+            AnnouncedBet::TiersDuCylindre,
+            AnnouncedBet::Orphelins,
+            AnnouncedBet::JeuZero,
+            neighbours(17, 3),
+        ];
+        bets.extend((0..=9).map(|digit| AnnouncedBet::Finale { digit }));
+        for low in 0..=9 {
+            bets.extend((low + 1..=9).map(|high| AnnouncedBet::FinaleACheval {
+                digits: [low, high],
+            }));
+        }
+        for bet in bets {
+            for (kind, chips) in bet.expand(EUROPEAN).unwrap() {
+                assert!(kind.is_valid(EUROPEAN), "{:?} of {:?}", kind, bet);
+                assert!(chips > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn voisins_du_zero() {
+        let bet = AnnouncedBet::VoisinsDuZero;
+        assert_eq!(chips(&bet), 9);
+        assert_eq!(
+            bet.covered(EUROPEAN),
+            sorted(vec![
+                22, 18, 29, 7, 28, 12, 35, 3, 26, 0, 32, 15, 19, 4, 21, 2, 25
+            ])
+        );
+    }
+
+    #[test]
+    fn tiers_du_cylindre() {
+        let bet = AnnouncedBet::TiersDuCylindre;
+        assert_eq!(chips(&bet), 6);
+        assert_eq!(
+            bet.covered(EUROPEAN),
+            sorted(vec![27, 13, 36, 11, 30, 8, 23, 10, 5, 24, 16, 33])
+        );
+    }
+
+    #[test]
+    fn orphelins() {
+        let bet = AnnouncedBet::Orphelins;
+        assert_eq!(chips(&bet), 5);
+        assert_eq!(
+            bet.covered(EUROPEAN),
+            sorted(vec![1, 20, 14, 31, 9, 17, 34, 6])
+        );
+    }
+
+    #[test]
+    fn jeu_zero() {
+        let bet = AnnouncedBet::JeuZero;
+        assert_eq!(chips(&bet), 4);
+        assert_eq!(
+            bet.covered(EUROPEAN),
+            sorted(vec![12, 35, 3, 26, 0, 32, 15])
+        );
+    }
+
+    #[test]
+    fn voisins_tiers_and_orphelins_split_the_wheel() {
+        let mut covered: Vec<Pocket> = [
+            AnnouncedBet::VoisinsDuZero,
+            AnnouncedBet::TiersDuCylindre,
+            AnnouncedBet::Orphelins,
+        ]
+        .iter()
+        .flat_map(|bet| bet.covered(EUROPEAN))
+        .collect();
+        covered.sort();
+        assert_eq!(covered, (0..37).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn neighbours_wrap_around_the_wheel() {
+        let expand = |bet: AnnouncedBet| -> Vec<BetKind> {
+            bet.expand(EUROPEAN)
+                .unwrap()
+                .into_iter()
+                .map(|(kind, chips)| {
+                    assert_eq!(chips, 1);
+                    kind
+                })
+                .collect()
+        };
+        assert_eq!(
+            expand(neighbours(0, 2)),
+            [3, 26, 0, 32, 15].map(straight).to_vec()
+        );
+        assert_eq!(
+            expand(neighbours(26, 2)),
+            [35, 3, 26, 0, 32].map(straight).to_vec()
+        );
+        assert_eq!(
+            expand(AnnouncedBet::Neighbours {
+                number: 5,
+                neighbours: DEFAULT_NEIGHBOURS,
+            }),
+            [23, 10, 5, 24, 16].map(straight).to_vec()
+        );
+        assert_eq!(chips(&neighbours(32, MAX_NEIGHBOURS)), 19);
+    }
+
+    #[test]
+    fn neighbours_are_limited() {
+        assert!(neighbours(17, 0).expand(EUROPEAN).is_err());
+        assert!(neighbours(17, 10).expand(EUROPEAN).is_err());
+        assert!(neighbours(37, 2).expand(EUROPEAN).is_err());
+    }
+
+    #[test]
+    fn finales() {
+        for digit in 0..=9 {
+            let bet = AnnouncedBet::Finale { digit };
+            let expected: Vec<Pocket> = (0..=36).filter(|number| number % 10 == digit).collect();
+            assert_eq!(chips(&bet), expected.len() as i64);
+            assert_eq!(bet.covered(EUROPEAN), expected);
+        }
+        assert!(AnnouncedBet::Finale { digit: 10 }.expand(EUROPEAN).is_err());
+    }
+
+    #[test]
+    fn finales_a_cheval() {
+        let expand = |digits| AnnouncedBet::FinaleACheval { digits }.expand(EUROPEAN);
+        assert_eq!(
+            expand([3, 0]).unwrap(),
+            vec![
+                (split(0, 3), 1),
+                (split(10, 13), 1),
+                (split(20, 23), 1),
+                (split(30, 33), 1),
+            ]
+        );
+        assert_eq!(
+            expand([4, 7]).unwrap(),
+            vec![
+                (split(4, 7), 1),
+                (split(14, 17), 1),
+                (split(24, 27), 1),
+                (straight(34), 1),
+            ]
+        );
+        // 21 and 22 don't touch on the board.
+        assert_eq!(
+            expand([1, 2]).unwrap(),
+            vec![
+                (split(1, 2), 1),
+                (split(11, 12), 1),
+                (straight(21), 1),
+                (straight(22), 1),
+                (split(31, 32), 1),
+            ]
+        );
+        for digits in [[0, 3], [4, 7], [1, 2], [5, 9], [6, 8]] {
+            let covered = AnnouncedBet::FinaleACheval { digits }.covered(EUROPEAN);
+            let expected: Vec<Pocket> = (0..=36)
+                .filter(|number| digits.contains(&(number % 10)))
+                .collect();
+            assert_eq!(covered, expected, "{:?}", digits);
+        }
+        assert!(expand([5, 5]).is_err());
+        assert!(expand([9, 10]).is_err());
+    }
+
+    #[test]
+    fn not_on_american_tables() {
+        let bets = [
+            AnnouncedBet::VoisinsDuZero,
+            AnnouncedBet::TiersDuCylindre,
+            AnnouncedBet::Orphelins,
+            AnnouncedBet::JeuZero,
+            neighbours(0, 2),
+            AnnouncedBet::Finale { digit: 7 },
+            AnnouncedBet::FinaleACheval { digits: [0, 3] },
+        ];
+        for bet in bets {
+            assert!(bet.expand(Variant::American).is_err(), "{:?}", bet);
+            assert!(bet
+                .expand(Variant::French {
+                    rule: ZeroRule::LaPartage
+                })
+                .is_ok());
+        }
+    }
+}
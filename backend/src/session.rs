@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome},
+    Request,
+};
+use serde::Serialize;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::structs::{PlayerId, Timestamp};
+
+const TOKEN_VERSION: &str = "v1";
+const SESSION_LIFETIME: Timestamp = 7 * 24 * 60 * 60;
+
+/// Signs and checks session tokens. Tokens look like
+/// `v1.<player_id>.<expires>.<hex HMAC-SHA256 of everything before it>`.
+#[derive(Debug)]
+pub(crate) struct SessionKeys {
+    secret: Vec<u8>,
+}
+
+/// The identity a request was made with, taken from its token.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Session {
+    pub(crate) player_id: PlayerId,
+    pub(crate) expires: Timestamp,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct SessionToken {
+    pub(crate) token: Arc<str>,
+    pub(crate) player_id: PlayerId,
+    pub(crate) expires: Timestamp,
+}
+
+impl SessionKeys {
+    pub(crate) fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+        }
+    }
+
+    /// Keys that only live as long as the process, so every token dies with it.
+    pub(crate) fn random() -> Self {
+        let mut secret = vec![0; 32];
+        OsRng.fill_bytes(&mut secret);
+        Self::new(secret)
+    }
+
+    /// Starts a session for a brand new guest player.
+    pub(crate) fn guest(&self) -> SessionToken {
+        self.issue(Uuid::new_v4())
+    }
+
+    pub(crate) fn issue(&self, player_id: PlayerId) -> SessionToken {
+        let expires = chrono::offset::Utc::now().timestamp() + SESSION_LIFETIME;
+        let payload = format!("{}.{}.{}", TOKEN_VERSION, player_id, expires);
+        let signature = hex::encode(self.mac(&payload).finalize().into_bytes());
+        SessionToken {
+            token: format!("{}.{}", payload, signature).into(),
+            player_id,
+            expires,
+        }
+    }
+
+    pub(crate) fn verify(&self, token: &str) -> anyhow::Result<Session> {
+        let (payload, signature) = token
+            .rsplit_once('.')
+            .ok_or(anyhow::anyhow!("Malformed session token"))?;
+        self.mac(payload)
+            .verify_slice(&hex::decode(signature)?)
+            .map_err(|_| anyhow::anyhow!("Session token signature doesn't match"))?;
+
+        let mut parts = payload.split('.');
+        let (Some(TOKEN_VERSION), Some(player_id), Some(expires), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            anyhow::bail!("Malformed session token");
+        };
+        let session = Session {
+            player_id: player_id.parse()?,
+            expires: expires.parse()?,
+        };
+        if session.expires <= chrono::offset::Utc::now().timestamp() {
+            anyhow::bail!("Session token has expired");
+        }
+        Ok(session)
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+/// Reads the token from an `Authorization: Bearer` header or, since browsers
+/// can't set headers on websockets, a `token` query parameter.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Session {
+    type Error = anyhow::Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(keys) = request.rocket().state::<SessionKeys>() else {
+            return Outcome::Error((
+                Status::InternalServerError,
+                anyhow::anyhow!("Session keys are not managed"),
+            ));
+        };
+        let token = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .or_else(|| request.query_value::<&str>("token").and_then(Result::ok));
+        let Some(token) = token else {
+            return Outcome::Error((
+                Status::Unauthorized,
+                anyhow::anyhow!("Missing session token"),
+            ));
+        };
+        match keys.verify(token) {
+            Ok(session) => Outcome::Success(session),
+            Err(e) => Outcome::Error((Status::Unauthorized, e)),
+        }
+    }
+}
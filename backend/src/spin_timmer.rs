@@ -1,102 +1,143 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::sync::Arc;
+
+use rocket::tokio::time::Instant;
 
 use crate::{
-    helper::broadcast_response_message, judge::judge_player, structs, ws_messages::ResponseMessages,
-};
-use rocket::tokio::{
-    self, select,
-    sync::{
-        mpsc::{self, Sender},
-        Mutex,
-    },
-    time,
+    judge::{judge_player, pocket_color},
+    ledger::{self, Account},
+    storage::{self, PlayerSettlement},
+    structs::{RoundState, SpinResult, Table, Timestamp},
+    ws_messages::ResponseMessages,
 };
 
-use self::structs::{Player, PlayerId, Timestamp};
-
-const NUMBER_OF_OPTIONS: u32 = 37;
-
-pub(crate) enum SpinTimmerMessages {
-    NewRequest { timestamp: Timestamp },
-    SudoRequest,
+/// Starts the betting window after the first spin request of a round.
+pub(crate) fn open_betting(table: &mut Table, start: Timestamp) {
+    table.round.betting_closes = Some(Instant::now() + table.config.betting_window());
+    table.change_round_state(RoundState::BettingOpen);
+    table.broadcast(
+        None,
+        ResponseMessages::BeginSpinTimmer {
+            start,
+            server_seed_hash: table.fair_round.commitment(),
+        },
+    );
 }
 
-pub(crate) async fn spawn_spin_timmer(
-    last_timestamp: Arc<Mutex<Option<Timestamp>>>,
-    players: Arc<Mutex<HashMap<PlayerId, Player>>>,
-) -> Sender<SpinTimmerMessages> {
-    let (spin_timmer_channel_sender, mut spin_timmer_channel_receiver) =
-        mpsc::channel::<SpinTimmerMessages>(10);
-    let mut interval = time::interval(Duration::from_secs(60));
+/// Spins and settles the round, then opens the table for the next one even if
+/// settling failed.
+pub(crate) async fn play_round(table: &mut Table) {
+    table.flush_bets();
+    if let Err(e) = broadcast_spin_response_message(table).await {
+        log::error!("{}", e);
+    }
+    table.round.spin_requests.clear();
+    table.round.betting_closes = None;
+    table.change_round_state(RoundState::Idle);
+}
 
-    tokio::spawn(async move {
-        loop {
-            select! {
-                _ = interval.tick() => {
-                    if last_timestamp.lock().await.is_none() {
-                        continue;
-                    }
-                    if let Err(e) = broadcast_spin_response_message(players.clone()).await {
-                                                            log::error!("{}", e);
-                    }
-                    let mut last_timestamp_ref = last_timestamp.lock().await;
-                    *last_timestamp_ref = None;
-                }
-                Some(message) = spin_timmer_channel_receiver.recv() => {
-                    let mut last_timestamp_ref = last_timestamp.lock().await;
-                    match message {
-                        SpinTimmerMessages::NewRequest {timestamp} => {
-                            if  last_timestamp_ref.is_none() {
-                                interval.reset();
-                                *last_timestamp_ref = Some(timestamp);
-                                if let Err(e) = broadcast_response_message(players.clone(), None, ResponseMessages::BeginSpinTimmer {start: timestamp}).await {
-                                    log::error!("{}", e);
-                                }
-                            }
-                        }
-                        SpinTimmerMessages::SudoRequest => {
-                            if let Err(e) = broadcast_spin_response_message(players.clone()).await {
-                                log::error!("{}", e);
-                            }
-                            interval.reset();
-                            *last_timestamp_ref = None;
-                        }
-                    }
-                }
-            }
-        }
-    });
+pub(crate) async fn broadcast_spin_response_message(table: &mut Table) -> anyhow::Result<()> {
+    let variant = table.variant;
+    table.change_round_state(RoundState::NoMoreBets);
+    table.change_round_state(RoundState::Spinning);
 
-    spin_timmer_channel_sender
-}
+    let mut client_seeds: Vec<Arc<str>> = table
+        .players
+        .values()
+        .filter(|player| !player.ws_channel_sender.is_closed())
+        .filter_map(|player| player.client_seed.clone())
+        .collect();
+    client_seeds.sort();
+    let lucky_number =
+        table
+            .outcome_source
+            .next_pocket(variant.pockets(), &table.fair_round, &client_seeds);
+    let proof = table.fair_round.proof(client_seeds, variant);
+    table.fair_round = table.fair_round.next(table.outcome_source.server_seed());
+    let next_server_seed_hash = table.fair_round.commitment();
 
-pub(crate) async fn broadcast_spin_response_message(
-    players: Arc<Mutex<HashMap<PlayerId, Player>>>,
-) -> anyhow::Result<()> {
-    let lucky_number = rand::random::<u32>() % (NUMBER_OF_OPTIONS + 1);
+    table.change_round_state(RoundState::Settling);
 
-    let mut players_ref = players.lock().await;
-    for (_, player) in players_ref.iter_mut() {
+    let mut judged = Vec::new();
+    let mut player_settlements = Vec::new();
+    for (player_id, player) in table.players.iter_mut() {
         if player.ws_channel_sender.is_closed() {
             continue;
         }
 
-        let judgement = judge_player(&player.bets, lucky_number).await;
-        player.balance += judgement.winning_amount - judgement.bet_amount;
-        if player.balance < judgement.bet_amount {
-            player.bets = Vec::new();
+        let staked: Vec<_> = player
+            .bets
+            .iter()
+            .filter(|bet| !bet.imprisoned)
+            .map(|bet| (bet.kind.clone(), bet.amount))
+            .collect();
+        if !staked.is_empty() {
+            player.last_bets = staked;
         }
+        let judgement = judge_player(&mut player.bets, lucky_number, variant).await?;
+        player.balance = player
+            .balance
+            .checked_add(judgement.winning_amount)?
+            .checked_sub(judgement.bet_amount)?;
+        judged.push((*player_id, judgement.winning_amount, player.balance));
+        player_settlements.push(PlayerSettlement {
+            player_id: *player_id,
+            settlements: judgement.settlements,
+        });
+    }
+
+    let timestamp = chrono::offset::Utc::now().timestamp();
+    let table_id = table.table_id.clone();
+    let mut balances: Vec<_> = judged
+        .iter()
+        .map(|(player_id, _, balance)| (Account::player(&table_id, player_id), *balance))
+        .collect();
+    let saved = storage::blocking(&table.storage, move |storage| {
+        let round_id =
+            storage.save_round(&table_id, lucky_number, timestamp, &player_settlements)?;
+        // The round is booked either way.
+        if let Err(e) = ledger::reconcile(storage, &mut balances) {
+            log::error!("Failed to check balances against the ledger: {:?}", e);
+        }
+        Ok((round_id, balances))
+    })
+    .await;
+    let (round_id, balances): (_, Vec<_>) = match saved {
+        Ok((round_id, balances)) => (
+            Some(round_id),
+            balances.into_iter().map(|(_, balance)| balance).collect(),
+        ),
+        Err(e) => {
+            log::error!("Failed to save round: {:?}", e);
+            (
+                None,
+                judged.iter().map(|(_, _, balance)| *balance).collect(),
+            )
+        }
+    };
+    table.record_spin(SpinResult {
+        round_id,
+        lucky_number,
+        color: pocket_color(lucky_number),
+        timestamp,
+    });
+
+    for ((player_id, winning_amount, _), balance) in judged.into_iter().zip(balances) {
+        let Some(player) = table.players.get_mut(&player_id) else {
+            continue;
+        };
+        player.balance = balance;
+        // Settled bets come off the felt, `RebetLast` puts them down again.
+        let bets_cleared = player.bets.iter().any(|bet| !bet.imprisoned);
+        player.bets.retain(|bet| bet.imprisoned);
         let response_message = ResponseMessages::Spin {
             lucky_number,
-            winning_amount: judgement.winning_amount,
+            winning_amount,
             balance: player.balance,
-            bets_cleared: player.balance < judgement.bet_amount,
+            bets_cleared,
+            proof: proof.clone(),
+            next_server_seed_hash: next_server_seed_hash.clone(),
         };
-
-        player
-            .ws_channel_sender
-            .send(response_message.clone())
-            .await?;
+        table.notify(&player_id, response_message);
     }
     Ok(())
 }
@@ -0,0 +1,151 @@
+use std::cmp::Reverse;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    judge::{BetKind, Pocket, Variant},
+    racetrack::AnnouncedBet,
+    storage::Storage,
+    structs::TableId,
+};
+
+const DEFAULT_SPINS: u32 = 100;
+const MAX_SPINS: u32 = 1000;
+/// How many pockets are listed as hot and as cold.
+const HOT_COLD_POCKETS: usize = 5;
+
+/// What the last `spins` spins of a table came out as. Counts are out of
+/// `spins`.
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub(crate) struct Statistics {
+    pub(crate) spins: u32,
+    /// Most drawn pockets, most first, leaving out ones never drawn.
+    pub(crate) hot: Vec<PocketHits>,
+    /// Least drawn pockets, least first, including ones never drawn.
+    pub(crate) cold: Vec<PocketHits>,
+    pub(crate) red: u32,
+    pub(crate) black: u32,
+    /// 0, and 00 on American wheels.
+    pub(crate) zero: u32,
+    /// Dozen 1 first.
+    pub(crate) dozens: [u32; 3],
+    /// Column 1 first.
+    pub(crate) columns: [u32; 3],
+    /// Only for single zero wheels.
+    pub(crate) sectors: Option<SectorHits>,
+    pub(crate) longest_streaks: Streaks,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, JsonSchema)]
+pub(crate) struct PocketHits {
+    pub(crate) pocket: Pocket,
+    pub(crate) hits: u32,
+}
+
+/// Spins that landed in each part of the racetrack.
+#[derive(Debug, Serialize, Clone, Copy, JsonSchema)]
+pub(crate) struct SectorHits {
+    pub(crate) voisins_du_zero: u32,
+    pub(crate) tiers_du_cylindre: u32,
+    pub(crate) orphelins: u32,
+}
+
+/// Most spins in a row that won each even-money bet.
+#[derive(Debug, Serialize, Clone, Copy, JsonSchema)]
+pub(crate) struct Streaks {
+    pub(crate) red: u32,
+    pub(crate) black: u32,
+    pub(crate) even: u32,
+    pub(crate) odd: u32,
+    pub(crate) low: u32,
+    pub(crate) high: u32,
+}
+
+/// Statistics over the latest `spins` rounds of `table_id`, or a sensible
+/// number of them when not asked for.
+pub(crate) fn table_statistics(
+    storage: &dyn Storage,
+    table_id: &TableId,
+    variant: Variant,
+    spins: Option<u32>,
+) -> anyhow::Result<Statistics> {
+    let spins = spins.unwrap_or(DEFAULT_SPINS).clamp(1, MAX_SPINS);
+    let lucky_numbers = storage.lucky_numbers(table_id, spins)?;
+    Ok(Statistics::from_lucky_numbers(&lucky_numbers, variant))
+}
+
+impl Statistics {
+    pub(crate) fn from_lucky_numbers(lucky_numbers: &[Pocket], variant: Variant) -> Self {
+        let count = |kind: BetKind| hits_on(lucky_numbers, &kind.covered(variant));
+
+        let mut hits: Vec<PocketHits> = (0..variant.pockets())
+            .map(|pocket| PocketHits {
+                pocket,
+                hits: hits_on(lucky_numbers, &[pocket]),
+            })
+            .collect();
+        hits.sort_by_key(|pocket_hits| (pocket_hits.hits, pocket_hits.pocket));
+        let cold = hits.iter().copied().take(HOT_COLD_POCKETS).collect();
+        hits.sort_by_key(|pocket_hits| (Reverse(pocket_hits.hits), pocket_hits.pocket));
+        let hot = hits
+            .iter()
+            .copied()
+            .filter(|pocket_hits| pocket_hits.hits > 0)
+            .take(HOT_COLD_POCKETS)
+            .collect();
+
+        let red = count(BetKind::Red);
+        let black = count(BetKind::Black);
+        let sectors = (variant != Variant::American).then(|| {
+            let count = |bet: AnnouncedBet| hits_on(lucky_numbers, &bet.covered(variant));
+            SectorHits {
+                voisins_du_zero: count(AnnouncedBet::VoisinsDuZero),
+                tiers_du_cylindre: count(AnnouncedBet::TiersDuCylindre),
+                orphelins: count(AnnouncedBet::Orphelins),
+            }
+        });
+
+        let streak = |kind: BetKind| {
+            let covered = kind.covered(variant);
+            let mut longest = 0;
+            let mut current = 0;
+            for pocket in lucky_numbers {
+                current = if covered.contains(pocket) {
+                    current + 1
+                } else {
+                    0
+                };
+                longest = longest.max(current);
+            }
+            longest
+        };
+
+        Self {
+            spins: lucky_numbers.len() as u32,
+            hot,
+            cold,
+            red,
+            black,
+            zero: lucky_numbers.len() as u32 - red - black,
+            dozens: [1, 2, 3].map(|dozen| count(BetKind::Dozen { dozen })),
+            columns: [1, 2, 3].map(|column| count(BetKind::Column { column })),
+            sectors,
+            longest_streaks: Streaks {
+                red: streak(BetKind::Red),
+                black: streak(BetKind::Black),
+                even: streak(BetKind::Even),
+                odd: streak(BetKind::Odd),
+                low: streak(BetKind::Low),
+                high: streak(BetKind::High),
+            },
+        }
+    }
+}
+
+fn hits_on(lucky_numbers: &[Pocket], covered: &[Pocket]) -> u32 {
+    lucky_numbers
+        .iter()
+        .filter(|pocket| covered.contains(pocket))
+        .count() as u32
+}
@@ -0,0 +1,470 @@
+use std::{
+    fmt::Debug,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use rocket::{serde::json, tokio::task};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::{
+    judge::{pocket_color, Pocket, Settlement, Variant},
+    ledger::{self, Account, EntryId, LedgerEntry, PostedEntry},
+    money::Money,
+    structs::{PlayerId, SpinResult, TableId, Timestamp, SPIN_HISTORY},
+    table_config::TableConfig,
+};
+
+pub(crate) type RoundId = i64;
+
+/// Table as it was last persisted, with everyone who ever sat at it.
+#[derive(Debug)]
+pub(crate) struct StoredTable {
+    pub(crate) table_id: TableId,
+    pub(crate) variant: Variant,
+    pub(crate) config: TableConfig,
+    pub(crate) players: Vec<StoredPlayer>,
+    /// Newest first.
+    pub(crate) history: Vec<SpinResult>,
+}
+
+#[derive(Debug)]
+pub(crate) struct StoredPlayer {
+    pub(crate) player_id: PlayerId,
+    pub(crate) name: String,
+    /// Derived from the ledger.
+    pub(crate) balance: Money,
+}
+
+/// Everything one player staked and got back in a round.
+#[derive(Debug)]
+pub(crate) struct PlayerSettlement {
+    pub(crate) player_id: PlayerId,
+    pub(crate) settlements: Vec<Settlement>,
+}
+
+/// Keeps players, the wallet ledger and round history across restarts.
+///
+/// Calls block, so async code makes them through `blocking`.
+pub(crate) trait Storage: Send + Sync + Debug {
+    fn save_table(
+        &self,
+        table_id: &TableId,
+        variant: Variant,
+        config: &TableConfig,
+    ) -> anyhow::Result<()>;
+
+    fn save_player(
+        &self,
+        table_id: &TableId,
+        player_id: &PlayerId,
+        name: &str,
+    ) -> anyhow::Result<()>;
+
+    /// Appends `entries` in one go, skipping any whose idempotency key was
+    /// already posted.
+    fn post(&self, entries: &[LedgerEntry]) -> anyhow::Result<()>;
+
+    /// Records a spin and posts the ledger entries settling it, keyed on the
+    /// round, all at once.
+    fn save_round(
+        &self,
+        table_id: &TableId,
+        lucky_number: Pocket,
+        timestamp: Timestamp,
+        players: &[PlayerSettlement],
+    ) -> anyhow::Result<RoundId>;
+
+    /// Everything credited to each of `accounts` minus everything debited from
+    /// it, read in one transaction.
+    fn balances(&self, accounts: &[Account]) -> anyhow::Result<Vec<Money>>;
+
+    /// Entries touching `account`, newest first, starting below `before`.
+    fn transactions(
+        &self,
+        account: &Account,
+        before: Option<EntryId>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<PostedEntry>>;
+
+    /// The table stored under `table_id` with its players, if there is one.
+    fn load_table(&self, table_id: &TableId) -> anyhow::Result<Option<StoredTable>>;
+
+    fn variant(&self, table_id: &TableId) -> anyhow::Result<Option<Variant>>;
+
+    /// Winning pockets of the last `limit` rounds, newest first.
+    fn lucky_numbers(&self, table_id: &TableId, limit: u32) -> anyhow::Result<Vec<Pocket>>;
+
+    /// The last `limit` rounds, newest first.
+    fn spin_history(&self, table_id: &TableId, limit: u32) -> anyhow::Result<Vec<SpinResult>>;
+}
+
+/// Runs `query` on a thread meant for blocking. Every table shares the one
+/// connection, so waiting on it mustn't hold up a worker thread.
+pub(crate) async fn blocking<T: Send + 'static>(
+    storage: &Arc<dyn Storage>,
+    query: impl FnOnce(&dyn Storage) -> anyhow::Result<T> + Send + 'static,
+) -> anyhow::Result<T> {
+    let storage = storage.clone();
+    task::spawn_blocking(move || query(storage.as_ref())).await?
+}
+
+#[derive(Debug)]
+pub(crate) struct SqliteStorage {
+    connection: Mutex<Connection>,
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS tables (
+    id TEXT PRIMARY KEY,
+    variant TEXT NOT NULL,
+    config TEXT NOT NULL DEFAULT '{}'
+);
+CREATE TABLE IF NOT EXISTS players (
+    table_id TEXT NOT NULL REFERENCES tables(id),
+    id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    PRIMARY KEY (table_id, id)
+);
+CREATE TABLE IF NOT EXISTS rounds (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    table_id TEXT NOT NULL REFERENCES tables(id),
+    lucky_number INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS settlements (
+    round_id INTEGER NOT NULL REFERENCES rounds(id),
+    player_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    returned INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS ledger (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    idempotency_key TEXT NOT NULL UNIQUE,
+    kind TEXT NOT NULL,
+    debit TEXT NOT NULL,
+    credit TEXT NOT NULL,
+    amount INTEGER NOT NULL CHECK (amount > 0),
+    timestamp INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS ledger_debit ON ledger (debit);
+CREATE INDEX IF NOT EXISTS ledger_credit ON ledger (credit);
+CREATE TRIGGER IF NOT EXISTS ledger_no_update BEFORE UPDATE ON ledger
+BEGIN
+    SELECT RAISE(ABORT, 'ledger entries are immutable');
+END;
+CREATE TRIGGER IF NOT EXISTS ledger_no_delete BEFORE DELETE ON ledger
+BEGIN
+    SELECT RAISE(ABORT, 'ledger entries are immutable');
+END;
+";
+
+impl SqliteStorage {
+    pub(crate) fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn post_entries(connection: &Connection, entries: &[LedgerEntry]) -> anyhow::Result<()> {
+    let mut statement = connection.prepare_cached(
+        "INSERT INTO ledger (idempotency_key, kind, debit, credit, amount, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT (idempotency_key) DO NOTHING",
+    )?;
+    for entry in entries {
+        statement.execute(params![
+            entry.idempotency_key,
+            json::to_string(&entry.kind)?,
+            json::to_string(&entry.debit)?,
+            json::to_string(&entry.credit)?,
+            entry.amount,
+            entry.timestamp
+        ])?;
+    }
+    Ok(())
+}
+
+fn balance_of(connection: &Connection, account: &str) -> anyhow::Result<Money> {
+    Ok(connection.query_row(
+        "SELECT COALESCE(SUM(CASE WHEN credit = ?1 THEN amount ELSE -amount END), 0)
+         FROM ledger WHERE credit = ?1 OR debit = ?1",
+        [account],
+        |row| row.get(0),
+    )?)
+}
+
+fn lucky_numbers_of(
+    connection: &Connection,
+    table_id: &TableId,
+    limit: u32,
+) -> anyhow::Result<Vec<Pocket>> {
+    let mut statement = connection
+        .prepare("SELECT lucky_number FROM rounds WHERE table_id = ?1 ORDER BY id DESC LIMIT ?2")?;
+    let lucky_numbers = statement
+        .query_map(params![table_id, limit], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(lucky_numbers)
+}
+
+fn spin_history_of(
+    connection: &Connection,
+    table_id: &TableId,
+    limit: u32,
+) -> anyhow::Result<Vec<SpinResult>> {
+    let mut statement = connection.prepare(
+        "SELECT id, lucky_number, timestamp FROM rounds WHERE table_id = ?1
+         ORDER BY id DESC LIMIT ?2",
+    )?;
+    let history = statement
+        .query_map(params![table_id, limit], |row| {
+            let lucky_number = row.get(1)?;
+            Ok(SpinResult {
+                round_id: Some(row.get(0)?),
+                lucky_number,
+                color: pocket_color(lucky_number),
+                timestamp: row.get(2)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(history)
+}
+
+impl Storage for SqliteStorage {
+    fn save_table(
+        &self,
+        table_id: &TableId,
+        variant: Variant,
+        config: &TableConfig,
+    ) -> anyhow::Result<()> {
+        self.connection().execute(
+            "INSERT INTO tables (id, variant, config) VALUES (?1, ?2, ?3)
+             ON CONFLICT (id) DO UPDATE SET variant = excluded.variant, config = excluded.config",
+            params![
+                table_id,
+                json::to_string(&variant)?,
+                json::to_string(config)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn save_player(
+        &self,
+        table_id: &TableId,
+        player_id: &PlayerId,
+        name: &str,
+    ) -> anyhow::Result<()> {
+        self.connection().execute(
+            "INSERT INTO players (table_id, id, name) VALUES (?1, ?2, ?3)
+             ON CONFLICT (table_id, id) DO UPDATE SET name = excluded.name",
+            params![table_id, player_id.to_string(), name],
+        )?;
+        Ok(())
+    }
+
+    fn post(&self, entries: &[LedgerEntry]) -> anyhow::Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        post_entries(&transaction, entries)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn save_round(
+        &self,
+        table_id: &TableId,
+        lucky_number: Pocket,
+        timestamp: Timestamp,
+        players: &[PlayerSettlement],
+    ) -> anyhow::Result<RoundId> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO rounds (table_id, lucky_number, timestamp) VALUES (?1, ?2, ?3)",
+            params![table_id, lucky_number, timestamp],
+        )?;
+        let round_id = transaction.last_insert_rowid();
+
+        for player in players {
+            let player_id = player.player_id.to_string();
+            for settlement in &player.settlements {
+                transaction.execute(
+                    "INSERT INTO settlements (round_id, player_id, kind, amount, returned)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        round_id,
+                        player_id,
+                        json::to_string(&settlement.kind)?,
+                        settlement.amount,
+                        settlement.returned
+                    ],
+                )?;
+            }
+            let entries = ledger::round_entries(
+                &ledger::round_key(round_id),
+                table_id,
+                &player.player_id,
+                &player.settlements,
+            )?;
+            post_entries(&transaction, &entries)?;
+        }
+        transaction.commit()?;
+        Ok(round_id)
+    }
+
+    fn balances(&self, accounts: &[Account]) -> anyhow::Result<Vec<Money>> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        let balances = accounts
+            .iter()
+            .map(|account| balance_of(&transaction, &json::to_string(account)?))
+            .collect::<anyhow::Result<_>>()?;
+        transaction.commit()?;
+        Ok(balances)
+    }
+
+    fn transactions(
+        &self,
+        account: &Account,
+        before: Option<EntryId>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<PostedEntry>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT id, kind, CASE WHEN credit = ?1 THEN amount ELSE -amount END, timestamp
+             FROM ledger WHERE (credit = ?1 OR debit = ?1) AND id < ?2
+             ORDER BY id DESC LIMIT ?3",
+        )?;
+        let rows = statement.query_map(
+            params![
+                json::to_string(account)?,
+                before.unwrap_or(EntryId::MAX),
+                limit
+            ],
+            |row| {
+                Ok((
+                    row.get::<_, EntryId>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Money>(2)?,
+                    row.get::<_, Timestamp>(3)?,
+                ))
+            },
+        )?;
+        rows.map(|row| {
+            let (id, kind, amount, timestamp) = row?;
+            Ok(PostedEntry {
+                id,
+                kind: json::from_str(&kind)?,
+                amount,
+                timestamp,
+            })
+        })
+        .collect()
+    }
+
+    fn load_table(&self, table_id: &TableId) -> anyhow::Result<Option<StoredTable>> {
+        let connection = self.connection();
+        let table = connection
+            .query_row(
+                "SELECT variant, config FROM tables WHERE id = ?1",
+                [table_id],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()?;
+        let Some((variant, config)) = table else {
+            return Ok(None);
+        };
+
+        let mut players_statement =
+            connection.prepare("SELECT id, name FROM players WHERE table_id = ?1")?;
+        let players = players_statement
+            .query_map([table_id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .map(|row| {
+                let (player_id, name) = row?;
+                let player_id = player_id.parse()?;
+                let account = json::to_string(&Account::player(table_id, &player_id))?;
+                Ok(StoredPlayer {
+                    player_id,
+                    name,
+                    balance: balance_of(&connection, &account)?,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let history = spin_history_of(&connection, table_id, SPIN_HISTORY as u32)?;
+
+        Ok(Some(StoredTable {
+            table_id: table_id.clone(),
+            variant: json::from_str(&variant)?,
+            config: json::from_str(&config)?,
+            players,
+            history,
+        }))
+    }
+
+    fn variant(&self, table_id: &TableId) -> anyhow::Result<Option<Variant>> {
+        let variant = self
+            .connection()
+            .query_row(
+                "SELECT variant FROM tables WHERE id = ?1",
+                [table_id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        Ok(variant
+            .map(|variant| json::from_str(&variant))
+            .transpose()?)
+    }
+
+    fn lucky_numbers(&self, table_id: &TableId, limit: u32) -> anyhow::Result<Vec<Pocket>> {
+        lucky_numbers_of(&self.connection(), table_id, limit)
+    }
+
+    fn spin_history(&self, table_id: &TableId, limit: u32) -> anyhow::Result<Vec<SpinResult>> {
+        spin_history_of(&self.connection(), table_id, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judge::BetKind;
+
+    #[test]
+    fn identical_rounds_settle_separately() {
+        let storage = SqliteStorage::open(":memory:").unwrap();
+        let table_id: TableId = "rounds".to_owned();
+        let player_id = PlayerId::new_v4();
+        storage
+            .save_table(&table_id, Variant::European, &TableConfig::default())
+            .unwrap();
+        let players = [PlayerSettlement {
+            player_id,
+            settlements: vec![Settlement {
+                kind: BetKind::Straight { number: 17 },
+                amount: Money::new(10),
+                returned: Money::new(360),
+            }],
+        }];
+
+        // A reopened table dealt by a seeded source repeats its rounds exactly.
+        let first = storage.save_round(&table_id, 17, 0, &players).unwrap();
+        let second = storage.save_round(&table_id, 17, 0, &players).unwrap();
+        assert_ne!(first, second);
+        let account = Account::player(&table_id, &player_id);
+        assert_eq!(storage.balances(&[account]).unwrap()[0], Money::new(700));
+    }
+}
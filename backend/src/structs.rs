@@ -1,55 +1,138 @@
-use rocket::tokio::sync::{mpsc::Sender, Mutex};
+use rocket::tokio::{
+    sync::{
+        mpsc::{self, Sender},
+        Mutex,
+    },
+    time::Instant,
+};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     sync::Arc,
+    time::Duration,
 };
 use uuid::Uuid;
 
-use crate::{spin_timmer::SpinTimmerMessages, ws_messages::ResponseMessages};
+use crate::{
+    event_log::{Audience, EventLog},
+    fairness::FairRound,
+    helper::send_response_message,
+    judge::{BetKind, Pocket, PocketColor, Variant},
+    money::Money,
+    outcome_source::{OutcomeConfig, OutcomeSource},
+    protocol::RequestId,
+    storage::{RoundId, Storage, StoredTable},
+    table_actor::{spawn_table, TableCommand, TableHandle},
+    table_config::TableConfig,
+    ws_messages::{OutboundMessage, ResponseMessages},
+};
 
-pub(crate) const DEFAULT_BALANCE: i32 = 2500;
+pub(crate) const DEFAULT_BALANCE: Money = Money::new(2500);
+/// How many past results a table shows at a glance.
+pub(crate) const RECENT_LUCKY_NUMBERS: usize = 20;
+/// How many past spins a table keeps at hand.
+pub(crate) const SPIN_HISTORY: usize = 100;
 
 pub(crate) type Timestamp = i64;
 pub(crate) type TableId = String;
 pub(crate) type PlayerId = Uuid;
+pub(crate) type BetId = u64;
+pub(crate) type Tables = Arc<Mutex<HashMap<TableId, TableHandle>>>;
 
+/// Directory of running tables. The lock is only held to look a table up,
+/// open one or take an idle one out; everything else goes through the
+/// table's own task.
 #[derive(Debug)]
 pub(crate) struct Game {
-    pub(crate) tables: Arc<Mutex<HashMap<TableId, Table>>>,
+    pub(crate) tables: Tables,
+    pub(crate) outcome_config: OutcomeConfig,
+    pub(crate) storage: Arc<dyn Storage>,
+    /// How long a table with nobody connected keeps running.
+    pub(crate) idle_grace: Duration,
 }
 
+/// Everything about one table, owned by the task running it.
 #[derive(Debug)]
 pub(crate) struct Table {
-    pub(crate) players: Arc<Mutex<HashMap<PlayerId, Player>>>,
-    pub(crate) spin_requests: HashSet<PlayerId>,
-    pub(crate) spin_timmer: SpinTimmer,
+    pub(crate) table_id: TableId,
+    pub(crate) players: HashMap<PlayerId, Player>,
+    pub(crate) round: Round,
+    pub(crate) variant: Variant,
+    pub(crate) config: TableConfig,
+    pub(crate) outcome_source: Box<dyn OutcomeSource>,
+    pub(crate) fair_round: FairRound,
+    pub(crate) storage: Arc<dyn Storage>,
+    pub(crate) events: EventLog,
+    /// Newest first, at most `SPIN_HISTORY`.
+    pub(crate) history: VecDeque<SpinResult>,
+    /// Bets not yet shown to the rest of the table.
+    pub(crate) pending_bets: Vec<(PlayerId, Bet)>,
+    /// When `pending_bets` go out.
+    pub(crate) bets_broadcast_at: Option<Instant>,
+    pub(crate) next_bet_id: BetId,
+    /// Id of the request being handled, which `reply` echoes.
+    pub(crate) replying_to: Option<RequestId>,
 }
 
 #[derive(Debug)]
 pub(crate) struct Player {
-    pub(crate) ws_channel_sender: Sender<ResponseMessages>,
+    pub(crate) ws_channel_sender: Sender<OutboundMessage>,
     pub(crate) name: String,
     pub(crate) bets: Vec<Bet>,
-    pub(crate) balance: i32,
+    pub(crate) balance: Money,
+    pub(crate) client_seed: Option<Arc<str>>,
+    /// What they staked on the last round they played, for `RebetLast`.
+    pub(crate) last_bets: Vec<(BetKind, Money)>,
+    /// Their connection went quiet. The wheel doesn't wait on them to ask for
+    /// a spin, though their bets still play.
+    pub(crate) away: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct Bet {
-    pub(crate) label: String,
-    pub(crate) placement: Placement,
-    pub(crate) local_position: (i32, i32),
-    pub(crate) amount: i32,
+    /// Unique at the table.
+    pub(crate) id: BetId,
+    pub(crate) kind: BetKind,
+    pub(crate) amount: Money,
+    pub(crate) imprisoned: bool,
 }
 
-#[derive(Debug)]
-pub(crate) struct SpinTimmer {
-    pub(crate) spin_timmer_channel_sender: Sender<SpinTimmerMessages>,
-    #[allow(unused)]
-    pub(crate) last_timestamp: Arc<Mutex<Option<Timestamp>>>,
+/// The round being played at a table.
+#[derive(Debug, Default)]
+pub(crate) struct Round {
+    pub(crate) state: RoundState,
+    pub(crate) spin_requests: HashSet<PlayerId>,
+    /// When the wheel spins if not everyone asks for it first.
+    pub(crate) betting_closes: Option<Instant>,
+}
+
+/// How a past round came out.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub(crate) struct SpinResult {
+    /// Missing if the round couldn't be saved.
+    pub(crate) round_id: Option<RoundId>,
+    pub(crate) lucky_number: Pocket,
+    pub(crate) color: PocketColor,
+    pub(crate) timestamp: Timestamp,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, JsonSchema)]
+pub(crate) enum RoundState {
+    /// Nobody has asked for a spin yet.
+    #[default]
+    Idle,
+    /// Someone asked for a spin and the betting window is running.
+    BettingOpen,
+    /// Bets are locked in.
+    NoMoreBets,
+    /// The winning pocket is being drawn.
+    Spinning,
+    /// Bets are being paid out and recorded.
+    Settling,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, JsonSchema)]
 pub(crate) enum Placement {
     #[serde(rename = "topleft")]
     TopLeft,
@@ -71,63 +154,276 @@ pub(crate) enum Placement {
     Center,
 }
 
-impl Default for Game {
-    fn default() -> Self {
+impl Game {
+    pub(crate) fn new(
+        outcome_config: OutcomeConfig,
+        storage: Arc<dyn Storage>,
+        idle_grace: Duration,
+    ) -> Self {
         Self {
             tables: Arc::new(Mutex::new(HashMap::new())),
+            outcome_config,
+            storage,
+            idle_grace,
+        }
+    }
+
+    /// Builds a table around `players` and starts its task.
+    pub(crate) fn open_table(
+        &self,
+        table_id: &TableId,
+        variant: Variant,
+        config: TableConfig,
+        players: HashMap<PlayerId, Player>,
+        history: VecDeque<SpinResult>,
+    ) -> TableHandle {
+        let mut outcome_source = self.outcome_config.build();
+        let fair_round = FairRound::new(outcome_source.server_seed(), 0);
+        spawn_table(
+            Table {
+                table_id: table_id.clone(),
+                players,
+                round: Round::default(),
+                variant,
+                config,
+                outcome_source,
+                fair_round,
+                storage: self.storage.clone(),
+                events: EventLog::new(),
+                history,
+                pending_bets: Vec::new(),
+                bets_broadcast_at: None,
+                next_bet_id: 1,
+                replying_to: None,
+            },
+            self.tables.clone(),
+            self.idle_grace,
+        )
+    }
+
+    pub(crate) async fn table(&self, table_id: &TableId) -> anyhow::Result<TableHandle> {
+        self.tables
+            .lock()
+            .await
+            .get(table_id)
+            .cloned()
+            .ok_or(anyhow::anyhow!("Table not found"))
+    }
+
+    /// Reopens a table kept in storage with its players seated but
+    /// disconnected, so they can rejoin with their `player_id`.
+    pub(crate) fn reopen_table(&self, stored_table: StoredTable) -> TableHandle {
+        let players = stored_table
+            .players
+            .into_iter()
+            .map(|stored_player| {
+                let player = Player::new(
+                    disconnected_sender(),
+                    &stored_player.name,
+                    Vec::new(),
+                    stored_player.balance,
+                );
+                (stored_player.player_id, player)
+            })
+            .collect();
+        self.open_table(
+            &stored_table.table_id,
+            stored_table.variant,
+            stored_table.config,
+            players,
+            stored_table.history.into(),
+        )
+    }
+
+    /// Stops every running table. Whatever they hold is already in storage.
+    pub(crate) async fn close_tables(&self) {
+        let tables: Vec<TableHandle> = self.tables.lock().await.values().cloned().collect();
+        for table in tables {
+            let _ = table.send(TableCommand::Shutdown).await;
         }
     }
 }
 
+/// Stands in for the connection of a player who isn't connected.
+pub(crate) fn disconnected_sender() -> Sender<OutboundMessage> {
+    let (sender, _) = mpsc::channel(1);
+    sender
+}
+
 impl Table {
-    pub(crate) fn new(players: Arc<Mutex<HashMap<PlayerId, Player>>>, timmer: SpinTimmer) -> Self {
-        Self {
-            players,
-            spin_timmer: timmer,
-            spin_requests: HashSet::new(),
+    /// Sends `response_message` to one player if they are still connected.
+    pub(crate) fn reply(&self, player_id: &PlayerId, response_message: ResponseMessages) {
+        if let Some(player) = self.players.get(player_id) {
+            send_response_message(
+                &player.ws_channel_sender,
+                OutboundMessage::reply(self.replying_to.clone(), response_message),
+            );
         }
     }
+
+    /// Sends a table event to everyone but `except` and logs it for those who
+    /// missed it.
+    pub(crate) fn broadcast(
+        &mut self,
+        except: Option<&PlayerId>,
+        response_message: ResponseMessages,
+    ) {
+        let audience = except.map_or(Audience::Everyone, |except| Audience::Except(*except));
+        let event = self.events.record(audience, response_message);
+        for (player_id, player) in self.players.iter() {
+            if Some(player_id) == except {
+                continue;
+            }
+            send_response_message(&player.ws_channel_sender, event.clone());
+        }
+    }
+
+    /// Sends a table event meant for one player only, logging it like
+    /// `broadcast` does.
+    pub(crate) fn notify(&mut self, player_id: &PlayerId, response_message: ResponseMessages) {
+        let event = self
+            .events
+            .record(Audience::Only(*player_id), response_message);
+        if let Some(player) = self.players.get(player_id) {
+            send_response_message(&player.ws_channel_sender, event);
+        }
+    }
+
+    /// Shows a new bet to everyone else at the table, batched with others
+    /// placed before the next broadcast.
+    pub(crate) fn announce_bet(&mut self, player_id: &PlayerId, bet: &Bet) -> anyhow::Result<()> {
+        let interval = self.config.bet_broadcast_interval();
+        if interval.is_zero() {
+            self.broadcast_bet(player_id, bet);
+            return Ok(());
+        }
+
+        let pending = self
+            .pending_bets
+            .iter_mut()
+            .find(|(id, pending)| id == player_id && pending.kind == bet.kind);
+        match pending {
+            Some((_, pending)) => pending.amount = pending.amount.checked_add(bet.amount)?,
+            None => self.pending_bets.push((*player_id, bet.clone())),
+        }
+        self.bets_broadcast_at
+            .get_or_insert_with(|| Instant::now() + interval);
+        Ok(())
+    }
+
+    /// Shows that a bet came off the table. Bets waiting to be shown go out
+    /// first so nobody sees a bet removed before it was placed.
+    pub(crate) fn announce_removed_bet(&mut self, player_id: &PlayerId, bet: &Bet) {
+        self.flush_bets();
+        self.broadcast(
+            Some(player_id),
+            ResponseMessages::SomePlayerRemovedBet {
+                hash_id: sha256::digest(player_id.to_string()).into(),
+                bet: bet.into(),
+            },
+        );
+    }
+
+    pub(crate) fn announce_cleared_bets(&mut self, player_id: &PlayerId) {
+        self.pending_bets.retain(|(id, _)| id != player_id);
+        self.broadcast(
+            Some(player_id),
+            ResponseMessages::SomePlayerClearedBets {
+                hash_id: sha256::digest(player_id.to_string()).into(),
+            },
+        );
+    }
+
+    /// Sends out every bet still waiting for the next broadcast.
+    pub(crate) fn flush_bets(&mut self) {
+        self.bets_broadcast_at = None;
+        for (player_id, bet) in std::mem::take(&mut self.pending_bets) {
+            self.broadcast_bet(&player_id, &bet);
+        }
+    }
+
+    fn broadcast_bet(&mut self, player_id: &PlayerId, bet: &Bet) {
+        self.broadcast(
+            Some(player_id),
+            ResponseMessages::SomePlayerBet {
+                hash_id: sha256::digest(player_id.to_string()).into(),
+                bet: bet.into(),
+            },
+        );
+    }
+
+    /// A bet with the next free id.
+    pub(crate) fn new_bet(&mut self, kind: BetKind, amount: Money) -> Bet {
+        let id = self.next_bet_id;
+        self.next_bet_id += 1;
+        Bet {
+            id,
+            kind,
+            amount,
+            imprisoned: false,
+        }
+    }
+
+    pub(crate) fn record_spin(&mut self, spin: SpinResult) {
+        self.history.push_front(spin);
+        self.history.truncate(SPIN_HISTORY);
+    }
+
+    /// Nobody is connected and no round is under way.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.round.state == RoundState::Idle
+            && self
+                .players
+                .values()
+                .all(|player| player.ws_channel_sender.is_closed())
+    }
+
+    /// Whether someone asked for the spin and nobody present is left to.
+    pub(crate) fn everyone_requested_spin(&self) -> bool {
+        !self.round.spin_requests.is_empty()
+            && self
+                .players
+                .iter()
+                .filter(|(_, player)| player.is_present())
+                .all(|(player_id, _)| self.round.spin_requests.contains(player_id))
+    }
+
+    /// Moves the round to `state` and tells everyone at the table.
+    pub(crate) fn change_round_state(&mut self, state: RoundState) {
+        if self.round.state == state {
+            return;
+        }
+        self.round.state = state;
+        self.broadcast(None, ResponseMessages::RoundStateChanged { state });
+    }
 }
 
 impl Player {
     pub(crate) fn new(
-        ws_channel_sender: Sender<ResponseMessages>,
+        ws_channel_sender: Sender<OutboundMessage>,
         name: &str,
         bets: Vec<Bet>,
+        balance: Money,
     ) -> Self {
         Self {
             ws_channel_sender,
             name: name.to_owned(),
             bets,
-            balance: DEFAULT_BALANCE,
+            balance,
+            client_seed: None,
+            last_bets: Vec::new(),
+            away: false,
         }
     }
-}
 
-impl Bet {
-    pub(crate) fn new(
-        label: String,
-        placement: Placement,
-        local_position: (i32, i32),
-        amount: i32,
-    ) -> Self {
-        Self {
-            label,
-            placement,
-            local_position,
-            amount,
-        }
+    /// Connected and answering.
+    pub(crate) fn is_present(&self) -> bool {
+        !self.away && !self.ws_channel_sender.is_closed()
     }
 }
 
-impl SpinTimmer {
-    pub(crate) fn new(
-        sender_channel: Sender<SpinTimmerMessages>,
-        last_timestamp: Arc<Mutex<Option<Timestamp>>>,
-    ) -> Self {
-        Self {
-            spin_timmer_channel_sender: sender_channel,
-            last_timestamp,
-        }
+impl RoundState {
+    pub(crate) fn accepts_bets(&self) -> bool {
+        matches!(self, RoundState::Idle | RoundState::BettingOpen)
     }
 }
@@ -0,0 +1,201 @@
+use std::{sync::Arc, time::Duration};
+
+use rocket::tokio::{
+    self, select,
+    sync::{
+        mpsc::{self, Sender},
+        oneshot,
+    },
+    time::{self, Instant},
+};
+
+use crate::{
+    helper::send_response_message,
+    protocol::RequestId,
+    spin_timmer::play_round,
+    structs::{disconnected_sender, PlayerId, RoundState, Table, Tables},
+    ws_messages::{OutboundMessage, RequestMessages, ResponseMessages},
+    ws_messages_handler,
+};
+
+const TABLE_COMMAND_CAPACITY: usize = 256;
+
+/// What a connection can ask of the table it sits at.
+#[derive(Debug)]
+pub(crate) enum TableCommand {
+    /// Seats the player, or moves them onto a new connection. Replies whether
+    /// they got a seat.
+    Join {
+        player_id: PlayerId,
+        name: Arc<str>,
+        ws_channel_sender: Sender<OutboundMessage>,
+        request_id: Option<RequestId>,
+        joined: oneshot::Sender<bool>,
+    },
+    /// The connection `ws_channel_sender` belongs to has gone away.
+    Leave {
+        player_id: PlayerId,
+        ws_channel_sender: Sender<OutboundMessage>,
+    },
+    /// The connection `ws_channel_sender` belongs to went quiet, or came back.
+    Presence {
+        player_id: PlayerId,
+        ws_channel_sender: Sender<OutboundMessage>,
+        away: bool,
+    },
+    Request {
+        player_id: PlayerId,
+        request_id: Option<RequestId>,
+        request: RequestMessages,
+    },
+    /// Stops the table's task, e.g. when the server is going down.
+    Shutdown,
+}
+
+/// Cheap to clone way of talking to a running table.
+#[derive(Debug, Clone)]
+pub(crate) struct TableHandle {
+    sender: Sender<TableCommand>,
+}
+
+impl TableHandle {
+    pub(crate) async fn send(&self, command: TableCommand) -> anyhow::Result<()> {
+        self.sender
+            .send(command)
+            .await
+            .map_err(|_| anyhow::anyhow!("Table has shut down"))
+    }
+}
+
+/// Runs `table` on its own task. Commands are handled one at a time, so
+/// nothing else can touch the table while a round is being settled.
+///
+/// Once nobody has been connected for `idle_grace` the table takes itself out
+/// of `tables` and stops; the next join loads it back from storage.
+pub(crate) fn spawn_table(mut table: Table, tables: Tables, idle_grace: Duration) -> TableHandle {
+    let (sender, mut receiver) = mpsc::channel::<TableCommand>(TABLE_COMMAND_CAPACITY);
+
+    tokio::spawn(async move {
+        let mut idle_since = table.is_idle().then(Instant::now);
+        loop {
+            let betting_closes = table.round.betting_closes;
+            let bets_broadcast_at = table.bets_broadcast_at;
+            let evict_at = idle_since.map(|idle_since| idle_since + idle_grace);
+            select! {
+                _ = time::sleep_until(betting_closes.unwrap_or_else(Instant::now)), if betting_closes.is_some() => {
+                    if table.round.state == RoundState::BettingOpen {
+                        play_round(&mut table).await;
+                    }
+                }
+                _ = time::sleep_until(bets_broadcast_at.unwrap_or_else(Instant::now)), if bets_broadcast_at.is_some() => {
+                    table.flush_bets();
+                }
+                _ = time::sleep_until(evict_at.unwrap_or_else(Instant::now)), if evict_at.is_some() => {
+                    if table.is_idle() {
+                        break;
+                    }
+                }
+                command = receiver.recv() => {
+                    match command {
+                        None | Some(TableCommand::Shutdown) => break,
+                        Some(command) => {
+                            if let Err(e) = handle_command(&mut table, command).await {
+                                log::error!("{:?}", e);
+                            }
+                        }
+                    }
+                }
+            }
+            idle_since = table
+                .is_idle()
+                .then(|| idle_since.unwrap_or_else(Instant::now));
+        }
+
+        tables.lock().await.remove(&table.table_id);
+        receiver.close();
+        while let Some(command) = receiver.recv().await {
+            if let TableCommand::Join {
+                ws_channel_sender,
+                request_id,
+                joined,
+                ..
+            } = command
+            {
+                send_response_message(
+                    &ws_channel_sender,
+                    OutboundMessage::reply(
+                        request_id,
+                        ResponseMessages::TableClosed {
+                            table_id: table.table_id.clone(),
+                        },
+                    ),
+                );
+                let _ = joined.send(false);
+            }
+        }
+        log::info!("Closed table {}", table.table_id);
+    });
+
+    TableHandle { sender }
+}
+
+async fn handle_command(table: &mut Table, command: TableCommand) -> anyhow::Result<()> {
+    match command {
+        TableCommand::Join {
+            player_id,
+            name,
+            ws_channel_sender,
+            request_id,
+            joined,
+        } => {
+            table.replying_to = request_id;
+            let seated =
+                ws_messages_handler::seat_player(table, player_id, name, ws_channel_sender).await;
+            table.replying_to = None;
+            let _ = joined.send(seated?);
+        }
+        TableCommand::Leave {
+            player_id,
+            ws_channel_sender,
+        } => {
+            // They may have rejoined on another connection in the meantime.
+            let Some(player) = table.players.get_mut(&player_id) else {
+                return Ok(());
+            };
+            if !player.ws_channel_sender.same_channel(&ws_channel_sender) {
+                return Ok(());
+            }
+            player.ws_channel_sender = disconnected_sender();
+            ws_messages_handler::leave_table(table, &player_id).await;
+        }
+        TableCommand::Presence {
+            player_id,
+            ws_channel_sender,
+            away,
+        } => {
+            let Some(player) = table.players.get(&player_id) else {
+                return Ok(());
+            };
+            if !player.ws_channel_sender.same_channel(&ws_channel_sender) {
+                return Ok(());
+            }
+            ws_messages_handler::mark_away(table, &player_id, away).await;
+        }
+        TableCommand::Request {
+            player_id,
+            request_id,
+            request,
+        } => {
+            table.replying_to = request_id;
+            let handled =
+                ws_messages_handler::handle_table_request(table, &player_id, request).await;
+            if handled.is_err() {
+                table.reply(&player_id, ResponseMessages::internal_error());
+            }
+            table.replying_to = None;
+            handled?;
+        }
+        TableCommand::Shutdown => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::{collections::HashMap, time::Duration};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{judge::BetType, money::Money, structs::DEFAULT_BALANCE};
+
+const DEFAULT_BETTING_WINDOW_SECS: u64 = 60;
+const MAX_BETTING_WINDOW_SECS: u64 = 60 * 60;
+const DEFAULT_BET_BROADCAST_INTERVAL_MS: u64 = 200;
+const MAX_BET_BROADCAST_INTERVAL_MS: u64 = 5000;
+
+/// House rules of one table, fixed when the table is created. Anything left
+/// out of a request keeps its default, which places no limits.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub(crate) struct TableConfig {
+    /// Stake allowed on a single spot, by bet type. Types without an entry
+    /// only need a positive amount.
+    pub(crate) bet_limits: HashMap<BetType, StakeLimit>,
+    /// Least a player must have on the table to request a spin.
+    pub(crate) table_min: Option<Money>,
+    /// Most a player may have on the table in one round.
+    pub(crate) table_max: Option<Money>,
+    /// How long the table waits for everyone after the first spin request.
+    pub(crate) betting_window_secs: u64,
+    /// Connected players the table seats at once.
+    pub(crate) max_players: Option<usize>,
+    pub(crate) starting_balance: Money,
+    /// How often bets are shown to the rest of the table. Bets placed in
+    /// between go out together, 0 shows each one straight away.
+    pub(crate) bet_broadcast_interval_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub(crate) struct StakeLimit {
+    pub(crate) min: Option<Money>,
+    pub(crate) max: Option<Money>,
+}
+
+impl Default for TableConfig {
+    fn default() -> Self {
+        Self {
+            bet_limits: HashMap::new(),
+            table_min: None,
+            table_max: None,
+            betting_window_secs: DEFAULT_BETTING_WINDOW_SECS,
+            max_players: None,
+            starting_balance: DEFAULT_BALANCE,
+            bet_broadcast_interval_ms: DEFAULT_BET_BROADCAST_INTERVAL_MS,
+        }
+    }
+}
+
+impl TableConfig {
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        for (bet_type, limit) in &self.bet_limits {
+            check_range(&format!("{:?} limit", bet_type), limit.min, limit.max)?;
+        }
+        check_range("Table limit", self.table_min, self.table_max)?;
+        if !(1..=MAX_BETTING_WINDOW_SECS).contains(&self.betting_window_secs) {
+            anyhow::bail!(
+                "Betting window must be between 1 and {} seconds",
+                MAX_BETTING_WINDOW_SECS
+            );
+        }
+        if self.max_players == Some(0) {
+            anyhow::bail!("A table must seat at least one player");
+        }
+        if self.starting_balance < Money::ZERO {
+            anyhow::bail!("Starting balance can't be negative");
+        }
+        if self.bet_broadcast_interval_ms > MAX_BET_BROADCAST_INTERVAL_MS {
+            anyhow::bail!(
+                "Bet broadcast interval can be at most {} milliseconds",
+                MAX_BET_BROADCAST_INTERVAL_MS
+            );
+        }
+        Ok(())
+    }
+
+    pub(crate) fn betting_window(&self) -> Duration {
+        Duration::from_secs(self.betting_window_secs)
+    }
+
+    pub(crate) fn bet_broadcast_interval(&self) -> Duration {
+        Duration::from_millis(self.bet_broadcast_interval_ms)
+    }
+
+    pub(crate) fn limit(&self, bet_type: BetType) -> Option<&StakeLimit> {
+        self.bet_limits.get(&bet_type)
+    }
+}
+
+fn check_range(what: &str, min: Option<Money>, max: Option<Money>) -> anyhow::Result<()> {
+    if min.is_some_and(|min| !min.is_positive()) || max.is_some_and(|max| !max.is_positive()) {
+        anyhow::bail!("{} must be positive", what);
+    }
+    if let (Some(min), Some(max)) = (min, max) {
+        if min > max {
+            anyhow::bail!(
+                "{} has a minimum of {} above its maximum of {}",
+                what,
+                min,
+                max
+            );
+        }
+    }
+    Ok(())
+}
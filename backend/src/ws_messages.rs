@@ -1,110 +1,501 @@
 use std::sync::Arc;
 
-use serde::{Deserialize, Serialize};
+use rocket::{
+    serde::json::{self, json},
+    tokio::time::Instant,
+};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{Deserialize, Serialize, Serializer};
 
-use crate::structs::{self, PlayerId, TableId};
+use crate::{
+    bet_validator::RejectReason,
+    event_log::Seq,
+    fairness,
+    judge::{BetKind, Pocket, Variant},
+    ledger::{EntryId, EntryKind, PostedEntry},
+    money::Money,
+    protocol::RequestId,
+    racetrack::AnnouncedBet,
+    statistics::Statistics,
+    structs::{self, BetId, PlayerId, RoundState, SpinResult, TableId, RECENT_LUCKY_NUMBERS},
+    table_config::TableConfig,
+};
 
 use self::structs::{Placement, Timestamp};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub(crate) enum RequestMessages {
+    /// Picks the protocol spoken on the connection, before joining a table.
+    Hello {
+        protocol_version: u32,
+        client_name: Option<Arc<str>>,
+    },
     JoinTable {
-        table_id: TableId,
-        player_id: Option<PlayerId>,
-        name: Arc<str>,
+        #[serde(flatten)]
+        request: JoinTableRequest,
     },
     AddBet {
-        label: String,
-        placement: Placement,
-        local_position: (i32, i32),
-        amount: i32,
+        #[serde(flatten)]
+        spot: BetSpot,
+        amount: Money,
     },
     ClearBets,
+    /// Places a racetrack bet as the board bets it stands for, staking `unit`
+    /// on each chip.
+    AnnouncedBet {
+        bet: AnnouncedBet,
+        unit: Money,
+    },
+    /// Takes back the latest bet still on the table.
+    UndoBet,
+    RemoveBet {
+        bet_id: BetId,
+    },
+    /// Places the bets of the last round played again.
+    RebetLast,
+    /// Places every bet on the table again.
+    DoubleBets,
     RequestSpin,
     GetStatus,
+    GetTableInfo,
     ListPlayers,
+    SetClientSeed {
+        client_seed: Option<Arc<str>>,
+    },
+    VerifySpin {
+        #[serde(flatten)]
+        proof: VerifySpinRequest,
+    },
+    GetTransactions {
+        before: Option<EntryId>,
+        limit: Option<u32>,
+    },
+    /// The last `limit` spins, newest first, 20 unless asked.
+    GetHistory {
+        limit: Option<u32>,
+    },
+    /// Numbers drawn over the last `spins` spins, 100 unless asked.
+    GetStatistics {
+        spins: Option<u32>,
+    },
+    /// Sent after joining again to get the events missed since `last_seq`.
+    Resume {
+        last_seq: Seq,
+    },
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Deserialize, Clone, JsonSchema)]
+pub(crate) struct VerifySpinRequest {
+    pub(crate) server_seed: Arc<str>,
+    pub(crate) client_seeds: Vec<Arc<str>>,
+    pub(crate) nonce: u64,
+    #[serde(default)]
+    pub(crate) variant: Variant,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub(crate) struct JoinTableRequest {
+    pub(crate) table_id: TableId,
+    pub(crate) name: Arc<str>,
+    /// Only used when the table doesn't exist yet.
+    #[serde(default)]
+    pub(crate) variant: Variant,
+    /// Only used when the table doesn't exist yet.
+    #[serde(default)]
+    pub(crate) config: TableConfig,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub(crate) enum BetSpot {
+    Kind {
+        kind: BetKind,
+    },
+    Board {
+        label: String,
+        placement: Placement,
+        local_position: (i32, i32),
+    },
+}
+
+/// What goes down the socket. Table events carry their `seq`, replies the id
+/// of the request they answer when it had one.
+#[derive(Debug, Clone)]
+pub(crate) struct OutboundMessage {
+    pub(crate) seq: Option<Seq>,
+    pub(crate) request_id: Option<RequestId>,
+    pub(crate) message: ResponseMessages,
+}
+
+/// A `V1` table event, its `seq` next to the message.
+#[derive(Serialize)]
+pub(crate) struct SequencedMessage<'a> {
+    seq: Seq,
+    #[serde(flatten)]
+    message: &'a ResponseMessages,
+}
+
+/// Derived, the flattened message would lose `seq`.
+impl JsonSchema for SequencedMessage<'_> {
+    fn schema_name() -> String {
+        "SequencedMessage".to_owned()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let message = generator.subschema_for::<ResponseMessages>();
+        let seq = generator.subschema_for::<Seq>();
+        json::from_value(json!({
+            "description": "A `V1` table event, its `seq` next to the message.",
+            "allOf": [
+                message,
+                {"type": "object", "properties": {"seq": seq}, "required": ["seq"]},
+            ],
+        }))
+        .expect("the schema is a schema")
+    }
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
 pub(crate) enum ResponseMessages {
+    /// The protocol the connection speaks from this message on.
+    Hello {
+        protocol_version: u32,
+    },
     JoinTable {
         player_id: PlayerId,
+        variant: Variant,
     },
     Status {
         status: Status,
     },
+    TableInfo {
+        table: TableInfo,
+    },
+    /// Everything needed to draw the table, sent on joining and instead of
+    /// replaying events when too many were missed.
+    TableSnapshot {
+        snapshot: TableSnapshot,
+    },
     AddBet {
         bet: Bet,
-        balance: i32,
-        total_bet: i32,
+        balance: Money,
+        total_bet: Money,
+    },
+    BetRejected {
+        reason_code: RejectReason,
+        detail: Arc<str>,
+    },
+    RemoveBet {
+        bet: Bet,
+        balance: Money,
+        total_bet: Money,
     },
     ClearBets,
     Spin {
         lucky_number: u32,
-        winning_amount: i32,
-        balance: i32,
+        winning_amount: Money,
+        balance: Money,
+        /// Their settled bets came off the table, as everyone's do after a
+        /// spin. Imprisoned bets stay.
         bets_cleared: bool,
+        proof: SpinProof,
+        next_server_seed_hash: Arc<str>,
     },
     BeginSpinTimmer {
         start: Timestamp,
+        server_seed_hash: Arc<str>,
+    },
+    RoundStateChanged {
+        state: RoundState,
+    },
+    SetClientSeed {
+        client_seed: Option<Arc<str>>,
+    },
+    VerifySpin {
+        #[serde(flatten)]
+        result: VerifySpinResult,
+    },
+    Transactions {
+        transactions: Vec<Transaction>,
+        /// Pass as `before` to fetch the next page, absent on the last one.
+        next_before: Option<EntryId>,
+    },
+    Statistics {
+        statistics: Statistics,
+    },
+    History {
+        spins: Vec<SpinResult>,
     },
     SomePlayerJoined {
         hash_id: Arc<str>,
         name: Arc<str>,
-        bet_amount: i32,
+        bet_amount: Money,
     },
     SomePlayerLeft {
         hash_id: Arc<str>,
     },
+    /// Someone else's connection went quiet, or came back. The wheel doesn't
+    /// wait on players who are away.
+    SomePlayerPresence {
+        hash_id: Arc<str>,
+        away: bool,
+    },
+    /// Someone else put chips down. Chips put on the same spot in quick
+    /// succession arrive as one bet.
+    SomePlayerBet {
+        hash_id: Arc<str>,
+        bet: Bet,
+    },
+    /// Someone else took back one bet. Match it by `kind`, as the chips may
+    /// have been shown merged with others.
+    SomePlayerRemovedBet {
+        hash_id: Arc<str>,
+        bet: Bet,
+    },
+    /// Someone else took back their bets, leaving any imprisoned ones.
+    SomePlayerClearedBets {
+        hash_id: Arc<str>,
+    },
+    /// The table stopped before it could take the request. Joining again
+    /// reopens it.
+    TableClosed {
+        table_id: TableId,
+    },
     ListPlayers {
         players: Vec<Player>,
     },
+    /// The request that caused it couldn't be done. `msg` is for people,
+    /// `code` for clients.
     Error {
+        code: ErrorCode,
         msg: Arc<str>,
     },
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+pub(crate) enum ErrorCode {
+    /// The request couldn't be read.
+    BadRequest,
+    UnsupportedProtocol,
+    /// Asked for something that has to happen before joining a table.
+    AlreadyJoined,
+    NotJoined,
+    BadTableConfig,
+    TableFull,
+    BettingClosed,
+    SpinAlreadyRequested,
+    /// There are no bets for the request to work on.
+    NoBets,
+    BetNotFound,
+    BetImprisoned,
+    BelowTableMinimum,
+    InvalidClientSeed,
+    /// Something went wrong on the server's side.
+    Internal,
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
 pub(crate) struct Status {
     pub(crate) bets: Vec<Bet>,
-    pub(crate) balance: i32,
+    pub(crate) balance: Money,
+    pub(crate) spin_requested: bool,
+    pub(crate) round_state: RoundState,
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub(crate) struct TableInfo {
+    pub(crate) table_id: TableId,
+    pub(crate) variant: Variant,
+    pub(crate) config: TableConfig,
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub(crate) struct TableSnapshot {
+    /// Resume from here.
+    pub(crate) seq: Seq,
+    pub(crate) table: TableInfo,
+    pub(crate) round_state: RoundState,
+    /// When the wheel spins unless everyone asks for it sooner.
+    pub(crate) betting_closes: Option<Timestamp>,
+    pub(crate) server_seed_hash: Arc<str>,
+    /// Newest first.
+    pub(crate) recent_lucky_numbers: Vec<Pocket>,
+    pub(crate) players: Vec<SeatedPlayer>,
+    /// The joining player's own view.
+    pub(crate) status: Status,
+}
+
+/// A player as everyone at the table sees them.
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub(crate) struct SeatedPlayer {
+    pub(crate) name: Arc<str>,
+    pub(crate) id_hash: Arc<str>,
+    pub(crate) bets: Vec<Bet>,
     pub(crate) spin_requested: bool,
+    pub(crate) away: bool,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub(crate) struct SpinProof {
+    pub(crate) server_seed: Arc<str>,
+    pub(crate) server_seed_hash: Arc<str>,
+    pub(crate) client_seeds: Vec<Arc<str>>,
+    pub(crate) nonce: u64,
+    pub(crate) variant: Variant,
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub(crate) struct VerifySpinResult {
+    pub(crate) lucky_number: Pocket,
+    pub(crate) server_seed_hash: Arc<str>,
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
 pub(crate) struct Bet {
-    pub(crate) label: String,
-    pub(crate) placement: Placement,
-    pub(crate) amount: i32,
+    pub(crate) id: BetId,
+    pub(crate) kind: BetKind,
+    pub(crate) amount: Money,
+    pub(crate) imprisoned: bool,
 }
 
-#[derive(Debug, Serialize, Clone)]
+/// A ledger entry on the player's wallet, negative when money left it.
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub(crate) struct Transaction {
+    pub(crate) id: EntryId,
+    pub(crate) kind: EntryKind,
+    pub(crate) amount: Money,
+    pub(crate) timestamp: Timestamp,
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
 pub(crate) struct Player {
     pub(crate) name: Arc<str>,
     pub(crate) id_hash: Arc<str>,
-    pub(crate) bet_amount: i32,
+    pub(crate) bet_amount: Money,
 }
 
-impl Bet {
-    pub(crate) fn new(label: String, placement: Placement, amount: i32) -> Self {
-        Self {
-            label,
-            placement,
-            amount,
+impl ResponseMessages {
+    pub(crate) fn error(code: ErrorCode, msg: impl Into<Arc<str>>) -> Self {
+        ResponseMessages::Error {
+            code,
+            msg: msg.into(),
         }
     }
+
+    pub(crate) fn internal_error() -> Self {
+        Self::error(ErrorCode::Internal, "Something went wrong, try again")
+    }
+}
+
+impl VerifySpinRequest {
+    pub(crate) fn verify(&self) -> anyhow::Result<VerifySpinResult> {
+        let (lucky_number, server_seed_hash) = fairness::verify(
+            &self.server_seed,
+            &self.client_seeds,
+            self.nonce,
+            self.variant.pockets(),
+        )?;
+        Ok(VerifySpinResult {
+            lucky_number,
+            server_seed_hash,
+        })
+    }
 }
 
 impl Status {
-    pub(crate) fn from_table(
-        table: &structs::Table,
+    pub(crate) fn from_round(
+        round: &structs::Round,
         player: &structs::Player,
         player_id: &structs::PlayerId,
     ) -> Self {
         Self {
             bets: player.bets.iter().map(|bet| bet.into()).collect(),
             balance: player.balance,
-            spin_requested: table.spin_requests.contains(player_id),
+            spin_requested: round.spin_requests.contains(player_id),
+            round_state: round.state,
+        }
+    }
+}
+
+impl OutboundMessage {
+    pub(crate) fn event(seq: Seq, message: ResponseMessages) -> Self {
+        Self {
+            seq: Some(seq),
+            request_id: None,
+            message,
+        }
+    }
+
+    pub(crate) fn reply(request_id: Option<RequestId>, message: ResponseMessages) -> Self {
+        Self {
+            seq: None,
+            request_id,
+            message,
+        }
+    }
+}
+
+impl From<ResponseMessages> for OutboundMessage {
+    fn from(message: ResponseMessages) -> Self {
+        Self::reply(None, message)
+    }
+}
+
+/// The first version of the protocol, which has no request ids.
+impl Serialize for OutboundMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.seq {
+            Some(seq) => SequencedMessage {
+                seq,
+                message: &self.message,
+            }
+            .serialize(serializer),
+            None => self.message.serialize(serializer),
+        }
+    }
+}
+
+impl TableSnapshot {
+    pub(crate) fn from_table(table: &structs::Table, player_id: &PlayerId) -> Option<Self> {
+        let player = table.players.get(player_id)?;
+        let betting_closes = table.round.betting_closes.map(|betting_closes| {
+            let left = betting_closes.saturating_duration_since(Instant::now());
+            chrono::offset::Utc::now().timestamp() + left.as_secs() as Timestamp
+        });
+        Some(Self {
+            seq: table.events.last_seq(),
+            table: TableInfo::from_table(table),
+            round_state: table.round.state,
+            betting_closes,
+            server_seed_hash: table.fair_round.commitment(),
+            recent_lucky_numbers: table
+                .history
+                .iter()
+                .take(RECENT_LUCKY_NUMBERS)
+                .map(|spin| spin.lucky_number)
+                .collect(),
+            players: table
+                .players
+                .iter()
+                .map(|(player_id, player)| SeatedPlayer {
+                    name: player.name.clone().into(),
+                    id_hash: sha256::digest(player_id.to_string()).into(),
+                    bets: player.bets.iter().map(|bet| bet.into()).collect(),
+                    spin_requested: table.round.spin_requests.contains(player_id),
+                    away: player.away,
+                })
+                .collect(),
+            status: Status::from_round(&table.round, player, player_id),
+        })
+    }
+}
+
+impl TableInfo {
+    pub(crate) fn from_table(table: &structs::Table) -> Self {
+        Self {
+            table_id: table.table_id.clone(),
+            variant: table.variant,
+            config: table.config.clone(),
         }
     }
 }
@@ -112,9 +503,10 @@ impl Status {
 impl From<&structs::Bet> for Bet {
     fn from(value: &structs::Bet) -> Self {
         Self {
-            label: value.label.clone(),
-            placement: value.placement.clone(),
+            id: value.id,
+            kind: value.kind.clone(),
             amount: value.amount,
+            imprisoned: value.imprisoned,
         }
     }
 }
@@ -124,7 +516,18 @@ impl Player {
         Self {
             name: player.name.clone().into(),
             id_hash: sha256::digest(player_id.to_string()).into(),
-            bet_amount: player.bets.iter().map(|bet| bet.amount).sum(),
+            bet_amount: Money::saturating_sum(player.bets.iter().map(|bet| bet.amount)),
+        }
+    }
+}
+
+impl From<PostedEntry> for Transaction {
+    fn from(value: PostedEntry) -> Self {
+        Self {
+            id: value.id,
+            kind: value.kind,
+            amount: value.amount,
+            timestamp: value.timestamp,
         }
     }
 }
@@ -1,26 +1,44 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
 
 use rocket::{
-    serde::json,
-    tokio::sync::{mpsc::Sender, Mutex},
+    serde::json::Value,
+    tokio::sync::{mpsc::Sender, oneshot},
 };
-use rocket_ws::Message;
-use uuid::Uuid;
 
 use crate::{
-    helper::broadcast_response_message,
-    spin_timmer,
-    structs::Placement,
-    ws_messages::{self, RequestMessages, ResponseMessages},
+    bet_validator,
+    event_log::Seq,
+    fairness,
+    helper::{send_response_message, CONNECTION_QUEUE_CAPACITY},
+    judge::BetKind,
+    ledger::{Account, EntryId, LedgerEntry},
+    money::Money,
+    protocol::{Protocol, RequestId, LATEST_PROTOCOL_VERSION, OLDEST_PROTOCOL_VERSION},
+    racetrack::AnnouncedBet,
+    session::Session,
+    spin_timmer, statistics,
+    storage::{self, Storage},
+    table_actor::TableCommand,
+    ws_messages::{
+        self, BetSpot, ErrorCode, JoinTableRequest, OutboundMessage, RequestMessages,
+        ResponseMessages, TableSnapshot, VerifySpinRequest,
+    },
     ArcGame,
 };
 
-use crate::structs::{Bet, Player, PlayerId, SpinTimmer, Table, TableId};
+use crate::structs::{
+    BetId, Player, PlayerId, RoundState, Table, TableId, RECENT_LUCKY_NUMBERS, SPIN_HISTORY,
+};
 
-use self::spin_timmer::SpinTimmerMessages;
+const DEFAULT_TRANSACTIONS_PAGE: u32 = 20;
+const MAX_TRANSACTIONS_PAGE: u32 = 100;
 
 pub(crate) async fn handle_close(
     game: ArcGame,
+    ws_channel_sender: Sender<OutboundMessage>,
     current_player_id: &Option<PlayerId>,
     current_table_id: &Option<TableId>,
 ) -> anyhow::Result<()> {
@@ -30,437 +48,888 @@ pub(crate) async fn handle_close(
     let current_table_id = current_table_id.as_ref().unwrap();
     let current_player_id = current_player_id.as_ref().unwrap();
 
-    let tables = game.tables.lock().await;
-    let table = tables
-        .get(current_table_id)
-        .ok_or(anyhow::anyhow!("Table not found"))?;
+    // A table that already closed has nobody left to tell.
+    let Ok(table) = game.table(current_table_id).await else {
+        return Ok(());
+    };
+    let _ = table
+        .send(TableCommand::Leave {
+            player_id: *current_player_id,
+            ws_channel_sender,
+        })
+        .await;
+    Ok(())
+}
 
-    broadcast_response_message(
-        table.players.clone(),
-        Some(vec![current_player_id.to_owned()]),
-        ResponseMessages::SomePlayerLeft {
-            hash_id: sha256::digest(current_player_id.to_string()).into(),
-        },
-    )
-    .await?;
+/// Tells the table whether the connection has gone quiet or come back.
+pub(crate) async fn set_away(
+    game: ArcGame,
+    ws_channel_sender: Sender<OutboundMessage>,
+    current_player_id: &Option<PlayerId>,
+    current_table_id: &Option<TableId>,
+    away: bool,
+) -> anyhow::Result<()> {
+    let (Some(current_player_id), Some(current_table_id)) = (current_player_id, current_table_id)
+    else {
+        return Ok(());
+    };
+
+    let Ok(table) = game.table(current_table_id).await else {
+        return Ok(());
+    };
+    let _ = table
+        .send(TableCommand::Presence {
+            player_id: *current_player_id,
+            ws_channel_sender,
+            away,
+        })
+        .await;
     Ok(())
 }
 
+/// Handles what the connection can do on its own and hands everything else to
+/// the table it has joined.
 pub(crate) async fn handle(
-    message: Message,
+    message: anyhow::Result<Value>,
     game: ArcGame,
-    ws_channel_sender: Sender<ResponseMessages>,
+    ws_channel_sender: Sender<OutboundMessage>,
+    session: &Session,
+    protocol: &mut Protocol,
     current_player_id: &mut Option<PlayerId>,
     current_table_id: &mut Option<TableId>,
 ) -> anyhow::Result<()> {
-    let request_message: RequestMessages = match message {
-        Message::Text(text) => match json::from_str(&text) {
-            Ok(req) => req,
-            Err(e) => {
-                ws_channel_sender
-                    .send(ResponseMessages::Error {
-                        msg: format!("Bad Request: {:?}", e.to_string()).into(),
-                    })
-                    .await?;
-                return Ok(());
-            }
-        },
-        _ => return Ok(()),
+    let (request_id, request_message) = match message {
+        Ok(value) => {
+            let (request_id, request_message) = protocol.decode(value);
+            (request_id, request_message.map_err(anyhow::Error::from))
+        }
+        Err(e) => (None, Err(e)),
     };
-
-    match request_message {
-        RequestMessages::JoinTable {
-            table_id,
-            player_id,
-            name,
-        } => {
-            join_table(
-                game,
-                ws_channel_sender,
-                current_player_id,
-                current_table_id,
-                table_id,
-                player_id,
-                &name,
-            )
-            .await?;
+    let request_message = match request_message {
+        Ok(req) => req,
+        Err(e) => {
+            send_response_message(
+                &ws_channel_sender,
+                OutboundMessage::reply(
+                    request_id,
+                    ResponseMessages::error(
+                        ErrorCode::BadRequest,
+                        format!("Bad Request: {:?}", e.to_string()),
+                    ),
+                ),
+            );
+            return Ok(());
         }
-        RequestMessages::GetStatus => {
-            if current_player_id.is_none() || current_table_id.is_none() {
-                ws_channel_sender
-                    .send(ResponseMessages::Error {
-                        msg: "No table has been joined".into(),
-                    })
-                    .await?;
-                return Ok(());
+    };
+
+    let handled: anyhow::Result<()> = async {
+        match request_message {
+            RequestMessages::Hello {
+                protocol_version,
+                client_name,
+            } => {
+                hello(
+                    ws_channel_sender.clone(),
+                    protocol,
+                    current_table_id,
+                    request_id.clone(),
+                    protocol_version,
+                    client_name,
+                )
+                .await?;
             }
-            let current_table_id = current_table_id.as_ref().unwrap();
-            let curent_player_id = current_player_id.as_ref().unwrap();
-            get_status(
-                game,
-                ws_channel_sender,
-                &curent_player_id,
-                &current_table_id,
-            )
-            .await?;
-        }
-        RequestMessages::AddBet {
-            label,
-            placement,
-            local_position,
-            amount,
-        } => {
-            if current_player_id.is_none() || current_table_id.is_none() {
-                ws_channel_sender
-                    .send(ResponseMessages::Error {
-                        msg: "No table has been joined".into(),
-                    })
-                    .await?;
-                return Ok(());
+            RequestMessages::JoinTable { request } => {
+                join_table(
+                    game,
+                    ws_channel_sender.clone(),
+                    session,
+                    current_player_id,
+                    current_table_id,
+                    request_id.clone(),
+                    request,
+                )
+                .await?;
             }
-            let current_table_id = current_table_id.as_ref().unwrap();
-            let curent_player_id = current_player_id.as_ref().unwrap();
-            add_bet(
-                game,
-                ws_channel_sender,
-                curent_player_id,
-                current_table_id,
-                &label,
-                placement,
-                local_position,
-                amount,
-            )
-            .await?;
-        }
-        RequestMessages::ClearBets => {
-            if current_player_id.is_none() || current_table_id.is_none() {
-                ws_channel_sender
-                    .send(ResponseMessages::Error {
-                        msg: "No table has been joined".into(),
-                    })
-                    .await?;
-                return Ok(());
+            RequestMessages::VerifySpin { proof } => {
+                verify_spin(ws_channel_sender.clone(), request_id.clone(), proof).await?;
             }
-            let current_table_id = current_table_id.as_ref().unwrap();
-            let curent_player_id = current_player_id.as_ref().unwrap();
-            clear_bets(
-                game,
-                ws_channel_sender,
-                &curent_player_id,
-                &current_table_id,
-            )
-            .await?;
-        }
-        RequestMessages::RequestSpin => {
-            if current_player_id.is_none() || current_table_id.is_none() {
-                ws_channel_sender
-                    .send(ResponseMessages::Error {
-                        msg: "No table has been joined".into(),
-                    })
-                    .await?;
-                return Ok(());
+            request => {
+                if current_player_id.is_none() || current_table_id.is_none() {
+                    send_response_message(
+                        &ws_channel_sender,
+                        OutboundMessage::reply(
+                            request_id.clone(),
+                            ResponseMessages::error(
+                                ErrorCode::NotJoined,
+                                "No table has been joined",
+                            ),
+                        ),
+                    );
+                    return Ok(());
+                }
+                let table_id = current_table_id.clone().unwrap();
+                let curent_player_id = current_player_id.as_ref().unwrap();
+                let sent = match game.table(&table_id).await {
+                    Ok(table) => {
+                        table
+                            .send(TableCommand::Request {
+                                player_id: *curent_player_id,
+                                request_id: request_id.clone(),
+                                request,
+                            })
+                            .await
+                    }
+                    Err(e) => Err(e),
+                };
+                if sent.is_err() {
+                    *current_player_id = None;
+                    *current_table_id = None;
+                    send_response_message(
+                        &ws_channel_sender,
+                        OutboundMessage::reply(
+                            request_id.clone(),
+                            ResponseMessages::TableClosed { table_id },
+                        ),
+                    );
+                }
             }
-            let current_table_id = current_table_id.as_ref().unwrap();
-            let curent_player_id = current_player_id.as_ref().unwrap();
-            request_spin(
-                game,
-                ws_channel_sender,
-                &curent_player_id,
-                &current_table_id,
-            )
-            .await?;
+        };
+        Ok(())
+    }
+    .await;
+    // The client still gets an answer when the server fails them.
+    if let Err(e) = handled {
+        log::error!("{:?}", e);
+        send_response_message(
+            &ws_channel_sender,
+            OutboundMessage::reply(request_id, ResponseMessages::internal_error()),
+        );
+    }
+    Ok(())
+}
+
+/// Switches the connection to the protocol version the client asked for, as
+/// long as it hasn't joined a table yet.
+pub(crate) async fn hello(
+    ws_channel_sender: Sender<OutboundMessage>,
+    protocol: &mut Protocol,
+    current_table_id: &Option<TableId>,
+    request_id: Option<RequestId>,
+    protocol_version: u32,
+    client_name: Option<Arc<str>>,
+) -> anyhow::Result<()> {
+    let (code, msg) = match Protocol::from_version(protocol_version) {
+        _ if current_table_id.is_some() => (
+            ErrorCode::AlreadyJoined,
+            "Say Hello before joining a table".to_owned(),
+        ),
+        Some(version) => {
+            *protocol = version;
+            log::info!(
+                "{} speaks protocol version {}",
+                client_name.as_deref().unwrap_or("A client"),
+                protocol_version
+            );
+            send_response_message(
+                &ws_channel_sender,
+                OutboundMessage::reply(request_id, ResponseMessages::Hello { protocol_version }),
+            );
+            return Ok(());
         }
-        RequestMessages::ListPlayers => {
-            if current_player_id.is_none() || current_table_id.is_none() {
-                ws_channel_sender
-                    .send(ResponseMessages::Error {
-                        msg: "No table has been joined".into(),
-                    })
-                    .await?;
-                return Ok(());
-            }
-            let current_table_id = current_table_id.as_ref().unwrap();
-            let curent_player_id = current_player_id.as_ref().unwrap();
-            list_players(
-                game,
-                ws_channel_sender,
-                &curent_player_id,
-                &current_table_id,
-            )
-            .await?;
+        None => (
+            ErrorCode::UnsupportedProtocol,
+            format!(
+                "Protocol version {} is not spoken here, only {} to {}",
+                protocol_version, OLDEST_PROTOCOL_VERSION, LATEST_PROTOCOL_VERSION
+            ),
+        ),
+    };
+    send_response_message(
+        &ws_channel_sender,
+        OutboundMessage::reply(request_id, ResponseMessages::error(code, msg)),
+    );
+    Ok(())
+}
+
+/// Requests handled by the table's own task, for a player seated at it.
+pub(crate) async fn handle_table_request(
+    table: &mut Table,
+    player_id: &PlayerId,
+    request: RequestMessages,
+) -> anyhow::Result<()> {
+    match request {
+        RequestMessages::GetStatus => get_status(table, player_id)?,
+        RequestMessages::GetTableInfo => get_table_info(table, player_id),
+        RequestMessages::AddBet { spot, amount } => add_bet(table, player_id, spot, amount)?,
+        RequestMessages::ClearBets => clear_bets(table, player_id)?,
+        RequestMessages::AnnouncedBet { bet, unit } => announced_bet(table, player_id, bet, unit)?,
+        RequestMessages::UndoBet => undo_bet(table, player_id)?,
+        RequestMessages::RemoveBet { bet_id } => remove_bet(table, player_id, bet_id)?,
+        RequestMessages::RebetLast => rebet_last(table, player_id)?,
+        RequestMessages::DoubleBets => double_bets(table, player_id)?,
+        RequestMessages::RequestSpin => request_spin(table, player_id).await?,
+        RequestMessages::ListPlayers => list_players(table, player_id),
+        RequestMessages::SetClientSeed { client_seed } => {
+            set_client_seed(table, player_id, client_seed)?
+        }
+        RequestMessages::GetTransactions { before, limit } => {
+            get_transactions(table, player_id, before, limit).await?
         }
+        RequestMessages::GetHistory { limit } => get_history(table, player_id, limit),
+        RequestMessages::GetStatistics { spins } => get_statistics(table, player_id, spins).await?,
+        RequestMessages::Resume { last_seq } => resume(table, player_id, last_seq),
+        RequestMessages::Hello { .. }
+        | RequestMessages::JoinTable { .. }
+        | RequestMessages::VerifySpin { .. } => {}
     };
-    return Ok(());
+    Ok(())
 }
 
 pub(crate) async fn join_table(
     game: ArcGame,
-    ws_channel_sender: Sender<ResponseMessages>,
-    current_player_id: &mut Option<Uuid>,
+    ws_channel_sender: Sender<OutboundMessage>,
+    session: &Session,
+    current_player_id: &mut Option<PlayerId>,
     current_table_id: &mut Option<TableId>,
-    table_id: String,
-    player_id: Option<Uuid>,
-    name: &str,
+    request_id: Option<RequestId>,
+    request: JoinTableRequest,
 ) -> anyhow::Result<()> {
-    let player_id = player_id.unwrap_or(Uuid::new_v4());
-    let mut tables = game.tables.lock().await;
-    match tables.get_mut(&table_id) {
-        Some(table) => {
-            let mut players = table.players.lock().await;
-            match players.get_mut(&player_id) {
-                Some(player) => {
-                    player.ws_channel_sender = ws_channel_sender.clone();
-                }
-                None => {
-                    players.insert(
-                        player_id,
-                        Player::new(ws_channel_sender.clone(), name, Vec::new()),
+    let JoinTableRequest {
+        table_id,
+        name,
+        variant,
+        config,
+    } = request;
+    let player_id = session.player_id;
+
+    let open_table = game.tables.lock().await.get(&table_id).cloned();
+    let table = match open_table {
+        Some(table) => table,
+        None => {
+            // Storage is read without holding the tables lock, so another
+            // join may open the same table meanwhile; whichever inserts
+            // first wins and the other's load is dropped unopened.
+            let stored_id = table_id.clone();
+            let stored_table =
+                storage::blocking(&game.storage, move |storage| storage.load_table(&stored_id))
+                    .await?;
+            if stored_table.is_none() {
+                if let Err(e) = config.validate() {
+                    send_response_message(
+                        &ws_channel_sender,
+                        OutboundMessage::reply(
+                            request_id,
+                            ResponseMessages::error(
+                                ErrorCode::BadTableConfig,
+                                format!("Bad table config: {}", e),
+                            ),
+                        ),
                     );
+                    return Ok(());
                 }
+                let (saved_id, saved_config) = (table_id.clone(), config.clone());
+                storage::blocking(&game.storage, move |storage| {
+                    storage.save_table(&saved_id, variant, &saved_config)
+                })
+                .await?;
             }
+            let mut tables = game.tables.lock().await;
+            tables
+                .entry(table_id.clone())
+                .or_insert_with(|| match stored_table {
+                    Some(stored_table) => game.reopen_table(stored_table),
+                    None => {
+                        game.open_table(&table_id, variant, config, HashMap::new(), VecDeque::new())
+                    }
+                })
+                .clone()
+        }
+    };
+
+    let (joined_sender, joined_receiver) = oneshot::channel();
+    let sent = table
+        .send(TableCommand::Join {
+            player_id,
+            name,
+            ws_channel_sender: ws_channel_sender.clone(),
+            request_id: request_id.clone(),
+            joined: joined_sender,
+        })
+        .await;
+    // The table closed between being looked up and taking the join. If the
+    // join was already queued the table says so itself while closing.
+    if sent.is_err() {
+        send_response_message(
+            &ws_channel_sender,
+            OutboundMessage::reply(request_id, ResponseMessages::TableClosed { table_id }),
+        );
+        return Ok(());
+    }
+    if joined_receiver.await? {
+        *current_player_id = Some(player_id);
+        *current_table_id = Some(table_id);
+    }
+    Ok(())
+}
+
+/// Seats `player_id` at the table, or moves them onto a new connection, and
+/// returns whether there was room.
+pub(crate) async fn seat_player(
+    table: &mut Table,
+    player_id: PlayerId,
+    name: Arc<str>,
+    ws_channel_sender: Sender<OutboundMessage>,
+) -> anyhow::Result<bool> {
+    let seated = table
+        .players
+        .iter()
+        .filter(|(id, player)| **id != player_id && !player.ws_channel_sender.is_closed())
+        .count();
+    if table.config.max_players.is_some_and(|max| seated >= max) {
+        send_response_message(
+            &ws_channel_sender,
+            OutboundMessage::reply(
+                table.replying_to.clone(),
+                ResponseMessages::error(ErrorCode::TableFull, "Table is full"),
+            ),
+        );
+        return Ok(false);
+    }
+
+    match table.players.get_mut(&player_id) {
+        Some(player) => {
+            player.ws_channel_sender = ws_channel_sender;
+            player.away = false;
         }
         None => {
-            let last_timestamp = Arc::new(Mutex::new(None));
-            let mut players_hashmap = HashMap::new();
-            players_hashmap.insert(
-                player_id,
-                Player::new(ws_channel_sender.clone(), name, Vec::new()),
-            );
-            let players = Arc::new(Mutex::new(players_hashmap));
-
-            tables.insert(
-                table_id.clone(),
-                Table::new(
-                    players.clone(),
-                    SpinTimmer::new(
-                        spin_timmer::spawn_spin_timmer(last_timestamp.clone(), players.clone())
-                            .await,
-                        last_timestamp,
-                    ),
-                ),
+            let player = Player::new(
+                ws_channel_sender,
+                &name,
+                Vec::new(),
+                table.config.starting_balance,
             );
+            save_new_player(&table.storage, &table.table_id, &player_id, &player).await?;
+            table.players.insert(player_id, player);
         }
     }
-    ws_channel_sender
-        .send(ResponseMessages::JoinTable { player_id })
-        .await?;
-    *current_player_id = Some(player_id);
-    *current_table_id = Some(table_id.clone());
-
-    let tables = game.tables.lock().await;
-    let table = tables
-        .get(&table_id)
-        .ok_or(anyhow::anyhow!("Table not found"))?;
-    let bet_amount;
-    {
-        let players = table.players.lock().await;
-        let player = players
-            .get(&player_id)
-            .ok_or(anyhow::anyhow!("Player not found!"))?;
-        bet_amount = player.bets.iter().map(|bet| bet.amount).sum();
-    }
-    broadcast_response_message(
-        table.players.clone(),
-        Some(vec![player_id]),
+
+    table.reply(
+        &player_id,
+        ResponseMessages::JoinTable {
+            player_id,
+            variant: table.variant,
+        },
+    );
+
+    let bet_amount = table
+        .players
+        .get(&player_id)
+        .map(|player| Money::saturating_sum(player.bets.iter().map(|bet| bet.amount)))
+        .unwrap_or_default();
+    table.broadcast(
+        Some(&player_id),
         ResponseMessages::SomePlayerJoined {
             hash_id: sha256::digest(player_id.to_string()).into(),
-            name: name.into(),
+            name,
             bet_amount,
         },
-    )
-    .await?;
-    Ok(())
+    );
+    // After the broadcast so the snapshot's seq covers it.
+    if let Some(snapshot) = TableSnapshot::from_table(table, &player_id) {
+        table.reply(&player_id, ResponseMessages::TableSnapshot { snapshot });
+    }
+    Ok(true)
 }
 
-pub(crate) async fn get_status(
-    game: ArcGame,
-    ws_channel_sender: Sender<ResponseMessages>,
-    current_player_id: &PlayerId,
-    current_table_id: &TableId,
+pub(crate) async fn leave_table(table: &mut Table, player_id: &PlayerId) {
+    table.broadcast(
+        Some(player_id),
+        ResponseMessages::SomePlayerLeft {
+            hash_id: sha256::digest(player_id.to_string()).into(),
+        },
+    );
+    spin_if_everyone_requested(table).await;
+}
+
+pub(crate) async fn mark_away(table: &mut Table, player_id: &PlayerId, away: bool) {
+    let Some(player) = table.players.get_mut(player_id) else {
+        return;
+    };
+    if player.away == away {
+        return;
+    }
+    player.away = away;
+    table.broadcast(
+        Some(player_id),
+        ResponseMessages::SomePlayerPresence {
+            hash_id: sha256::digest(player_id.to_string()).into(),
+            away,
+        },
+    );
+    spin_if_everyone_requested(table).await;
+}
+
+/// The one player everyone was waiting on may just have stopped counting.
+async fn spin_if_everyone_requested(table: &mut Table) {
+    if table.round.state == RoundState::BettingOpen && table.everyone_requested_spin() {
+        spin_timmer::play_round(table).await;
+    }
+}
+
+/// Stores a newly seated player and credits their starting balance.
+async fn save_new_player(
+    storage: &Arc<dyn Storage>,
+    table_id: &TableId,
+    player_id: &PlayerId,
+    player: &Player,
 ) -> anyhow::Result<()> {
-    let tables = game.tables.lock().await;
-    let table = tables
-        .get(current_table_id)
-        .ok_or(anyhow::anyhow!("Table not found"))?;
-    let players = table.players.lock().await;
-    let player = players
+    let top_up = LedgerEntry::top_up(
+        format!("welcome:{}:{}", table_id, player_id),
+        table_id,
+        player_id,
+        player.balance,
+    )?;
+    let (table_id, player_id, name) = (table_id.clone(), *player_id, player.name.clone());
+    storage::blocking(storage, move |storage| {
+        storage.save_player(&table_id, &player_id, &name)?;
+        storage.post(&[top_up])
+    })
+    .await
+}
+
+pub(crate) fn get_status(table: &Table, current_player_id: &PlayerId) -> anyhow::Result<()> {
+    let player = table
+        .players
         .get(current_player_id)
         .ok_or(anyhow::anyhow!("Player not found!"))?;
     let resp = ResponseMessages::Status {
-        status: ws_messages::Status::from_table(table, player, current_player_id),
+        status: ws_messages::Status::from_round(&table.round, player, current_player_id),
     };
-    ws_channel_sender.send(resp).await?;
+    table.reply(current_player_id, resp);
     Ok(())
 }
 
-pub(crate) async fn add_bet(
-    game: ArcGame,
-    ws_channel_sender: Sender<ResponseMessages>,
+/// Tells the player why their bets can't change right now, if they can't.
+fn bets_locked(table: &Table, player_id: &PlayerId) -> bool {
+    let (code, msg) = if !table.round.state.accepts_bets() {
+        (ErrorCode::BettingClosed, "No more bets")
+    } else if table.round.spin_requests.contains(player_id) {
+        (
+            ErrorCode::SpinAlreadyRequested,
+            "Already requested for spin",
+        )
+    } else {
+        return false;
+    };
+    table.reply(player_id, ResponseMessages::error(code, msg));
+    true
+}
+
+pub(crate) fn get_table_info(table: &Table, current_player_id: &PlayerId) {
+    let resp = ResponseMessages::TableInfo {
+        table: ws_messages::TableInfo::from_table(table),
+    };
+    table.reply(current_player_id, resp);
+}
+
+pub(crate) fn add_bet(
+    table: &mut Table,
     current_player_id: &PlayerId,
-    current_table_id: &TableId,
-    label: &str,
-    placement: Placement,
-    local_position: (i32, i32),
-    amount: i32,
+    spot: BetSpot,
+    amount: Money,
 ) -> anyhow::Result<()> {
-    let tables = game.tables.lock().await;
-    let table = tables
-        .get(current_table_id)
-        .ok_or(anyhow::anyhow!("Table not found"))?;
-
-    if table.spin_requests.contains(current_player_id) {
-        ws_channel_sender
-            .send(ResponseMessages::Error {
-                msg: "Already requested for spin".into(),
-            })
-            .await?;
+    if bets_locked(table, current_player_id) {
         return Ok(());
     }
 
-    let mut players = table.players.lock().await;
-    let player = players
-        .get_mut(current_player_id)
-        .ok_or(anyhow::anyhow!("Player not found!"))?;
+    let kind = match bet_validator::resolve_spot(spot, table.variant) {
+        Ok(kind) => kind,
+        Err(rejection) => {
+            table.reply(current_player_id, rejection.into());
+            return Ok(());
+        }
+    };
 
-    let total_bet = player.bets.iter().map(|bet| bet.amount).sum::<i32>() + amount;
-    if total_bet > player.balance {
+    place_bets(table, current_player_id, vec![(kind, amount)])
+}
+
+pub(crate) fn announced_bet(
+    table: &mut Table,
+    current_player_id: &PlayerId,
+    bet: AnnouncedBet,
+    unit: Money,
+) -> anyhow::Result<()> {
+    if bets_locked(table, current_player_id) {
         return Ok(());
     }
 
-    player.bets.push(Bet::new(
-        label.to_string(),
-        placement,
-        local_position,
-        amount,
-    ));
-
-    let resp = ResponseMessages::AddBet {
-        bet: ws_messages::Bet::new(label.to_string(), placement, amount),
-        balance: player.balance,
-        total_bet,
+    let expanded = match bet.expand(table.variant) {
+        Ok(expanded) => expanded,
+        Err(rejection) => {
+            table.reply(current_player_id, rejection.into());
+            return Ok(());
+        }
     };
+    let stakes = expanded
+        .into_iter()
+        .map(|(kind, chips)| Ok((kind, unit.checked_mul(chips)?)))
+        .collect::<anyhow::Result<_>>()?;
+    place_bets(table, current_player_id, stakes)
+}
+
+/// Places every one of `stakes` or, if the table won't take one of them, none.
+/// Each placed bet is answered like a single `AddBet`.
+fn place_bets(
+    table: &mut Table,
+    current_player_id: &PlayerId,
+    stakes: Vec<(BetKind, Money)>,
+) -> anyhow::Result<()> {
+    let player = table
+        .players
+        .get(current_player_id)
+        .ok_or(anyhow::anyhow!("Player not found!"))?;
 
-    ws_channel_sender.send(resp).await?;
+    let mut placed: Vec<(BetKind, Money)> = player
+        .bets
+        .iter()
+        .map(|bet| (bet.kind.clone(), bet.amount))
+        .collect();
+    let mut total_bets = Vec::with_capacity(stakes.len());
+    for (kind, amount) in &stakes {
+        let checked =
+            bet_validator::check_stake(kind, *amount, &placed, player.balance, &table.config);
+        match checked {
+            Ok(total_bet) => total_bets.push(total_bet),
+            Err(rejection) => {
+                table.reply(current_player_id, rejection.into());
+                return Ok(());
+            }
+        }
+        placed.push((kind.clone(), *amount));
+    }
+
+    // Ids are only handed out once the whole batch is accepted.
+    for ((kind, amount), total_bet) in stakes.into_iter().zip(total_bets) {
+        let bet = table.new_bet(kind, amount);
+        let player = table
+            .players
+            .get_mut(current_player_id)
+            .ok_or(anyhow::anyhow!("Player not found!"))?;
+        player.bets.push(bet.clone());
+
+        let resp = ResponseMessages::AddBet {
+            bet: (&bet).into(),
+            balance: player.balance,
+            total_bet,
+        };
+        table.reply(current_player_id, resp);
+        table.announce_bet(current_player_id, &bet)?;
+    }
     Ok(())
 }
 
-pub(crate) async fn clear_bets(
-    game: ArcGame,
-    ws_channel_sender: Sender<ResponseMessages>,
+pub(crate) fn undo_bet(table: &mut Table, current_player_id: &PlayerId) -> anyhow::Result<()> {
+    let player = table
+        .players
+        .get(current_player_id)
+        .ok_or(anyhow::anyhow!("Player not found!"))?;
+    let Some(bet_id) = player
+        .bets
+        .iter()
+        .rev()
+        .find(|bet| !bet.imprisoned)
+        .map(|bet| bet.id)
+    else {
+        table.reply(
+            current_player_id,
+            ResponseMessages::error(ErrorCode::NoBets, "No bets to undo"),
+        );
+        return Ok(());
+    };
+    remove_bet(table, current_player_id, bet_id)
+}
+
+pub(crate) fn remove_bet(
+    table: &mut Table,
     current_player_id: &PlayerId,
-    current_table_id: &TableId,
+    bet_id: BetId,
 ) -> anyhow::Result<()> {
-    let tables = game.tables.lock().await;
-    let table = tables
-        .get(current_table_id)
-        .ok_or(anyhow::anyhow!("Table not found"))?;
-
-    if table.spin_requests.contains(current_player_id) {
-        ws_channel_sender
-            .send(ResponseMessages::Error {
-                msg: "No table has been joined".into(),
-            })
-            .await?;
+    if bets_locked(table, current_player_id) {
         return Ok(());
     }
 
-    let mut players = table.players.lock().await;
-    let player = players
+    let player = table
+        .players
         .get_mut(current_player_id)
         .ok_or(anyhow::anyhow!("Player not found!"))?;
-    player.bets = Vec::new();
-    ws_channel_sender.send(ResponseMessages::ClearBets).await?;
+    let (code, msg) = match player.bets.iter().position(|bet| bet.id == bet_id) {
+        Some(index) if !player.bets[index].imprisoned => {
+            let bet = player.bets.remove(index);
+            let resp = ResponseMessages::RemoveBet {
+                bet: (&bet).into(),
+                balance: player.balance,
+                total_bet: Money::checked_sum(player.bets.iter().map(|bet| bet.amount))?,
+            };
+            table.reply(current_player_id, resp);
+            table.announce_removed_bet(current_player_id, &bet);
+            return Ok(());
+        }
+        Some(_) => (
+            ErrorCode::BetImprisoned,
+            "Imprisoned bets stay on the table",
+        ),
+        None => (ErrorCode::BetNotFound, "Bet not found"),
+    };
+    table.reply(current_player_id, ResponseMessages::error(code, msg));
     Ok(())
 }
 
-pub(crate) async fn request_spin(
-    game: ArcGame,
-    ws_channel_sender: Sender<ResponseMessages>,
-    current_player_id: &PlayerId,
-    current_table_id: &TableId,
-) -> anyhow::Result<()> {
-    let mut tables = game.tables.lock().await;
-    let table = tables
-        .get_mut(current_table_id)
-        .ok_or(anyhow::anyhow!("Table not found"))?;
-
-    if table.spin_requests.contains(current_player_id) {
-        ws_channel_sender
-            .send(ResponseMessages::Error {
-                msg: "No table has been joined".into(),
-            })
-            .await?;
+pub(crate) fn rebet_last(table: &mut Table, current_player_id: &PlayerId) -> anyhow::Result<()> {
+    if bets_locked(table, current_player_id) {
         return Ok(());
     }
 
-    let players = table.players.lock().await;
-    let player = players
+    let player = table
+        .players
         .get(current_player_id)
         .ok_or(anyhow::anyhow!("Player not found!"))?;
-    if player.bets.len() == 0 {
-        ws_channel_sender
-            .send(ResponseMessages::Error {
-                msg: "No bets added".into(),
-            })
-            .await?;
+    if player.last_bets.is_empty() {
+        table.reply(
+            current_player_id,
+            ResponseMessages::error(ErrorCode::NoBets, "No bets from the last round"),
+        );
         return Ok(());
     }
+    let stakes = player.last_bets.clone();
+    place_bets(table, current_player_id, stakes)
+}
 
-    let number_of_requestables = players
+pub(crate) fn double_bets(table: &mut Table, current_player_id: &PlayerId) -> anyhow::Result<()> {
+    if bets_locked(table, current_player_id) {
+        return Ok(());
+    }
+
+    let player = table
+        .players
+        .get(current_player_id)
+        .ok_or(anyhow::anyhow!("Player not found!"))?;
+    let stakes: Vec<_> = player
+        .bets
         .iter()
-        .filter(|(_, player)| !player.ws_channel_sender.is_closed())
-        .count();
+        .filter(|bet| !bet.imprisoned)
+        .map(|bet| (bet.kind.clone(), bet.amount))
+        .collect();
+    if stakes.is_empty() {
+        table.reply(
+            current_player_id,
+            ResponseMessages::error(ErrorCode::NoBets, "No bets to double"),
+        );
+        return Ok(());
+    }
+    place_bets(table, current_player_id, stakes)
+}
 
-    if number_of_requestables == table.spin_requests.len() + 1 {
-        table
-            .spin_timmer
-            .spin_timmer_channel_sender
-            .send(SpinTimmerMessages::SudoRequest)
-            .await?;
-    } else {
-        table
-            .spin_timmer
-            .spin_timmer_channel_sender
-            .send(SpinTimmerMessages::NewRequest {
-                timestamp: chrono::offset::Utc::now().timestamp(),
-            })
-            .await?;
-        table.spin_requests.insert(current_player_id.to_owned());
+pub(crate) fn clear_bets(table: &mut Table, current_player_id: &PlayerId) -> anyhow::Result<()> {
+    if bets_locked(table, current_player_id) {
+        return Ok(());
     }
 
+    let player = table
+        .players
+        .get_mut(current_player_id)
+        .ok_or(anyhow::anyhow!("Player not found!"))?;
+    player.bets.retain(|bet| bet.imprisoned);
+    table.reply(current_player_id, ResponseMessages::ClearBets);
+    table.announce_cleared_bets(current_player_id);
     Ok(())
 }
 
-pub(crate) async fn list_players(
-    game: ArcGame,
-    ws_channel_sender: Sender<ResponseMessages>,
+pub(crate) async fn request_spin(
+    table: &mut Table,
     current_player_id: &PlayerId,
-    current_table_id: &TableId,
 ) -> anyhow::Result<()> {
-    let tables = game.tables.lock().await;
-    let table = tables
-        .get(current_table_id)
-        .ok_or(anyhow::anyhow!("Table not found"))?;
-
-    if table.spin_requests.contains(current_player_id) {
-        ws_channel_sender
-            .send(ResponseMessages::Error {
-                msg: "No table has been joined".into(),
-            })
-            .await?;
+    if bets_locked(table, current_player_id) {
+        return Ok(());
+    }
+
+    let player = table
+        .players
+        .get(current_player_id)
+        .ok_or(anyhow::anyhow!("Player not found!"))?;
+    if player.bets.is_empty() {
+        table.reply(
+            current_player_id,
+            ResponseMessages::error(ErrorCode::NoBets, "No bets added"),
+        );
         return Ok(());
     }
+    if let Some(table_min) = table.config.table_min {
+        let total_bet = Money::checked_sum(player.bets.iter().map(|bet| bet.amount))?;
+        if total_bet < table_min {
+            table.reply(
+                current_player_id,
+                ResponseMessages::error(
+                    ErrorCode::BelowTableMinimum,
+                    format!("Table needs at least {} in bets to spin", table_min),
+                ),
+            );
+            return Ok(());
+        }
+    }
+
+    table
+        .round
+        .spin_requests
+        .insert(current_player_id.to_owned());
+    if table.everyone_requested_spin() {
+        spin_timmer::play_round(table).await;
+    } else if table.round.betting_closes.is_none() {
+        spin_timmer::open_betting(table, chrono::offset::Utc::now().timestamp());
+    }
 
-    let players = table.players.lock().await;
+    Ok(())
+}
 
-    let players = players
+pub(crate) fn list_players(table: &Table, current_player_id: &PlayerId) {
+    if table.round.spin_requests.contains(current_player_id) {
+        table.reply(
+            current_player_id,
+            ResponseMessages::error(
+                ErrorCode::SpinAlreadyRequested,
+                "Already requested for spin",
+            ),
+        );
+        return;
+    }
+
+    let players = table
+        .players
         .iter()
         .map(|(player_id, player)| ws_messages::Player::from_player(player, player_id))
         .collect();
 
     let ws_channel_response_message = ws_messages::ResponseMessages::ListPlayers { players };
 
-    ws_channel_sender.send(ws_channel_response_message).await?;
+    table.reply(current_player_id, ws_channel_response_message);
+}
+
+pub(crate) fn set_client_seed(
+    table: &mut Table,
+    current_player_id: &PlayerId,
+    client_seed: Option<Arc<str>>,
+) -> anyhow::Result<()> {
+    if let Some(client_seed) = &client_seed {
+        if !fairness::is_valid_client_seed(client_seed) {
+            table.reply(
+                current_player_id,
+                ResponseMessages::error(
+                    ErrorCode::InvalidClientSeed,
+                    "Client seed must be 1 to 64 letters, digits, '-' or '_'",
+                ),
+            );
+            return Ok(());
+        }
+    }
+
+    if bets_locked(table, current_player_id) {
+        return Ok(());
+    }
+    let player = table
+        .players
+        .get_mut(current_player_id)
+        .ok_or(anyhow::anyhow!("Player not found!"))?;
+    player.client_seed = client_seed.clone();
 
+    table.reply(
+        current_player_id,
+        ResponseMessages::SetClientSeed { client_seed },
+    );
     Ok(())
 }
+
+pub(crate) async fn verify_spin(
+    ws_channel_sender: Sender<OutboundMessage>,
+    request_id: Option<RequestId>,
+    proof: VerifySpinRequest,
+) -> anyhow::Result<()> {
+    let resp = match proof.verify() {
+        Ok(result) => ResponseMessages::VerifySpin { result },
+        Err(e) => ResponseMessages::error(ErrorCode::BadRequest, format!("Bad Request: {}", e)),
+    };
+    send_response_message(&ws_channel_sender, OutboundMessage::reply(request_id, resp));
+    Ok(())
+}
+
+pub(crate) async fn get_transactions(
+    table: &mut Table,
+    current_player_id: &PlayerId,
+    before: Option<EntryId>,
+    limit: Option<u32>,
+) -> anyhow::Result<()> {
+    let limit = limit
+        .unwrap_or(DEFAULT_TRANSACTIONS_PAGE)
+        .clamp(1, MAX_TRANSACTIONS_PAGE);
+    let account = Account::player(&table.table_id, current_player_id);
+    let transactions = storage::blocking(&table.storage, move |storage| {
+        storage.transactions(&account, before, limit)
+    })
+    .await?;
+
+    let next_before = match transactions.len() == limit as usize {
+        true => transactions.last().map(|entry| entry.id),
+        false => None,
+    };
+    table.reply(
+        current_player_id,
+        ResponseMessages::Transactions {
+            transactions: transactions.into_iter().map(Into::into).collect(),
+            next_before,
+        },
+    );
+    Ok(())
+}
+
+pub(crate) fn get_history(table: &Table, current_player_id: &PlayerId, limit: Option<u32>) {
+    let limit = limit
+        .map_or(RECENT_LUCKY_NUMBERS, |limit| limit as usize)
+        .clamp(1, SPIN_HISTORY);
+    let spins = table.history.iter().take(limit).cloned().collect();
+    table.reply(current_player_id, ResponseMessages::History { spins });
+}
+
+pub(crate) async fn get_statistics(
+    table: &mut Table,
+    current_player_id: &PlayerId,
+    spins: Option<u32>,
+) -> anyhow::Result<()> {
+    let (table_id, variant) = (table.table_id.clone(), table.variant);
+    let statistics = storage::blocking(&table.storage, move |storage| {
+        statistics::table_statistics(storage, &table_id, variant, spins)
+    })
+    .await?;
+    table.reply(
+        current_player_id,
+        ResponseMessages::Statistics { statistics },
+    );
+    Ok(())
+}
+
+/// Replays the events `player_id` missed since `last_seq`, or sends a snapshot
+/// of the table when they can't all be replayed.
+pub(crate) fn resume(table: &Table, player_id: &PlayerId, last_seq: Seq) {
+    let Some(player) = table.players.get(player_id) else {
+        return;
+    };
+    match table.events.since(last_seq, player_id) {
+        Some(events) if events.len() <= CONNECTION_QUEUE_CAPACITY / 2 => {
+            for event in events {
+                send_response_message(&player.ws_channel_sender, event);
+            }
+        }
+        _ => {
+            if let Some(snapshot) = TableSnapshot::from_table(table, player_id) {
+                table.reply(player_id, ResponseMessages::TableSnapshot { snapshot });
+            }
+        }
+    }
+}
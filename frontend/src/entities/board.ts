@@ -1,24 +1,14 @@
 import { ScreenContext } from "../components/game-screen/game-screen";
+import { POCKET_COLORS, type Placement } from "../protocol";
 import { Chip } from "./chip";
 import { colors } from "./colors";
 import { Drawable, Sensible } from "./traits";
 
 const RED = colors.RED, BLACK = colors.BLACK;
 type BoxType = typeof RED | typeof BLACK;
-const BOX_COLOR_MAP: BoxType[] = [
-  RED, BLACK, RED,
-  BLACK, RED, BLACK,
-  RED, BLACK, RED,
-  BLACK, BLACK, RED,
-  BLACK, RED, BLACK,
-  RED, BLACK, RED,
-  RED, BLACK, RED,
-  BLACK, RED, BLACK,
-  RED, BLACK, RED,
-  BLACK, BLACK, RED,
-  BLACK, RED, BLACK,
-  RED, BLACK, RED,
-];
+const BOX_COLOR_MAP: BoxType[] = POCKET_COLORS
+  .slice(1, 37)
+  .map(color => color == "Red" ? RED : BLACK);
 const BOX_SIZE = 70;
 const LABEL_HEIGHT = 20;
 const LINE_WIDTH = 5;
@@ -34,16 +24,6 @@ const TOP = "top";
 const BOTTOM = "bottom";
 const CENTER = "center";
 
-type Placement = typeof TOP_LEFT |
-  typeof TOP_RIGHT |
-  typeof BOTTOM_LEFT |
-  typeof BOTTOM_RIGHT |
-  typeof LEFT |
-  typeof RIGHT |
-  typeof TOP |
-  typeof BOTTOM |
-  typeof CENTER;
-
 interface CollisionResult {
   label: string
   placement: Placement,
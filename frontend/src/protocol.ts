@@ -0,0 +1,575 @@
+// Generated by `cargo test -- --ignored write_protocol_types` in backend/. Do not edit.
+
+export const OLDEST_PROTOCOL_VERSION = 1;
+export const LATEST_PROTOCOL_VERSION = 2;
+
+/** Color of every pocket by number, 37 being 00. */
+export const POCKET_COLORS: PocketColor[] = ["Green", "Red", "Black", "Red", "Black", "Red", "Black", "Red", "Black", "Red", "Black", "Black", "Red", "Black", "Red", "Black", "Red", "Black", "Red", "Red", "Black", "Red", "Black", "Red", "Black", "Red", "Black", "Red", "Black", "Black", "Red", "Black", "Red", "Black", "Red", "Black", "Red", "Green"];
+
+/**
+ * Bets called by name on the racetrack of single zero tables. Each is placed as the board bets it stands for.
+ */
+export type AnnouncedBet =
+  | "VoisinsDuZero"
+  | "TiersDuCylindre"
+  | "Orphelins"
+  | "JeuZero"
+  | {
+    Neighbours: {
+      neighbours?: number;
+      number: number;
+    };
+  }
+  | {
+    Finale: {
+      digit: number;
+    };
+  }
+  | {
+    FinaleACheval: {
+      digits: number[];
+    };
+  };
+
+export type Bet = {
+  amount: number;
+  id: number;
+  imprisoned: boolean;
+  kind: BetKind;
+};
+
+/**
+ * Every bet the table understands, identified by what it covers rather than where the chip was dropped on the board.
+ */
+export type BetKind =
+  | "Red" | "Black" | "Even" | "Odd" | "Low" | "High"
+  | {
+    Straight: {
+      number: number;
+    };
+  }
+  | {
+    Split: {
+      numbers: number[];
+    };
+  }
+  | {
+    Street: {
+      first: number;
+    };
+  }
+  | {
+    Trio: {
+      numbers: number[];
+    };
+  }
+  | {
+    Corner: {
+      first: number;
+    };
+  }
+  | {
+    SixLine: {
+      first: number;
+    };
+  }
+  | "TopLine"
+  | "Basket"
+  | {
+    Column: {
+      column: number;
+    };
+  }
+  | {
+    Dozen: {
+      dozen: number;
+    };
+  };
+
+export type EntryKind = "Stake" | "Win" | "Refund" | "TopUp" | "Adjustment";
+
+export type ErrorCode = "UnsupportedProtocol" | "NotJoined" | "BadTableConfig" | "TableFull" | "BettingClosed" | "SpinAlreadyRequested" | "BetNotFound" | "BetImprisoned" | "BelowTableMinimum" | "InvalidClientSeed" | "BadRequest" | "AlreadyJoined" | "NoBets" | "Internal";
+
+/**
+ * A `V2` request.
+ */
+export type InboundEnvelope = {
+  id?: RequestId | null;
+  /**
+   * Fields of the request, left out when it has none.
+   */
+  payload?: unknown;
+  /**
+   * Name of the `RequestMessages` variant.
+   */
+  type: string;
+};
+
+/**
+ * A `V2` reply or table event.
+ */
+export type OutboundEnvelope = {
+  /**
+   * The `id` of the request replied to.
+   */
+  id?: RequestId | null;
+  /**
+   * Fields of the message, left out when it has none.
+   */
+  payload?: unknown;
+  /**
+   * Set on table events.
+   */
+  seq?: number | null;
+  /**
+   * Name of the `ResponseMessages` variant.
+   */
+  type: string;
+};
+
+export type Placement = "topleft" | "topright" | "bottomleft" | "bottomright" | "left" | "right" | "top" | "bottom" | "center";
+
+export type Player = {
+  bet_amount: number;
+  id_hash: string;
+  name: string;
+};
+
+export type PocketColor = "Red" | "Black" | "Green";
+
+export type PocketHits = {
+  hits: number;
+  pocket: number;
+};
+
+export type RejectReason = "UnknownLabel" | "InvalidPlacement" | "InvalidBet" | "NonPositiveAmount" | "InsufficientBalance" | "BelowMinimum" | "AboveMaximum" | "AboveTableMaximum";
+
+/**
+ * Picked by the client to match replies with the requests they answer.
+ */
+export type RequestId = number | string;
+
+export type RequestMessages =
+  | "ClearBets" | "RequestSpin" | "GetStatus" | "GetTableInfo" | "ListPlayers"
+  | {
+    Hello: {
+      client_name?: string | null;
+      protocol_version: number;
+    };
+  }
+  | {
+    JoinTable: {
+      /**
+       * Only used when the table doesn't exist yet.
+       */
+      config?: TableConfig;
+      name: string;
+      table_id: string;
+      /**
+       * Only used when the table doesn't exist yet.
+       */
+      variant?: Variant;
+    };
+  }
+  | {
+    AddBet:
+      | {
+        kind: BetKind;
+      }
+      | {
+        label: string;
+        local_position: [number, number];
+        placement: Placement;
+      };
+  }
+  | {
+    AnnouncedBet: {
+      bet: AnnouncedBet;
+      unit: number;
+    };
+  }
+  | "UndoBet"
+  | {
+    RemoveBet: {
+      bet_id: number;
+    };
+  }
+  | "RebetLast"
+  | "DoubleBets"
+  | {
+    SetClientSeed: {
+      client_seed?: string | null;
+    };
+  }
+  | {
+    VerifySpin: {
+      client_seeds: string[];
+      nonce: number;
+      server_seed: string;
+      variant?: Variant;
+    };
+  }
+  | {
+    GetTransactions: {
+      before?: number | null;
+      limit?: number | null;
+    };
+  }
+  | {
+    GetHistory: {
+      limit?: number | null;
+    };
+  }
+  | {
+    GetStatistics: {
+      spins?: number | null;
+    };
+  }
+  | {
+    Resume: {
+      last_seq: number;
+    };
+  };
+
+export type ResponseMessages =
+  | "ClearBets"
+  | {
+    Hello: {
+      protocol_version: number;
+    };
+  }
+  | {
+    JoinTable: {
+      player_id: string;
+      variant: Variant;
+    };
+  }
+  | {
+    Status: {
+      status: Status;
+    };
+  }
+  | {
+    TableInfo: {
+      table: TableInfo;
+    };
+  }
+  | {
+    TableSnapshot: {
+      snapshot: TableSnapshot;
+    };
+  }
+  | {
+    AddBet: {
+      balance: number;
+      bet: Bet;
+      total_bet: number;
+    };
+  }
+  | {
+    BetRejected: {
+      detail: string;
+      reason_code: RejectReason;
+    };
+  }
+  | {
+    RemoveBet: {
+      balance: number;
+      bet: Bet;
+      total_bet: number;
+    };
+  }
+  | {
+    Spin: {
+      balance: number;
+      /**
+       * Their settled bets came off the table, as everyone's do after a spin. Imprisoned bets stay.
+       */
+      bets_cleared: boolean;
+      lucky_number: number;
+      next_server_seed_hash: string;
+      proof: SpinProof;
+      winning_amount: number;
+    };
+  }
+  | {
+    BeginSpinTimmer: {
+      server_seed_hash: string;
+      start: number;
+    };
+  }
+  | {
+    RoundStateChanged: {
+      state: RoundState;
+    };
+  }
+  | {
+    SetClientSeed: {
+      client_seed?: string | null;
+    };
+  }
+  | {
+    VerifySpin: {
+      lucky_number: number;
+      server_seed_hash: string;
+    };
+  }
+  | {
+    Transactions: {
+      /**
+       * Pass as `before` to fetch the next page, absent on the last one.
+       */
+      next_before?: number | null;
+      transactions: Transaction[];
+    };
+  }
+  | {
+    Statistics: {
+      statistics: Statistics;
+    };
+  }
+  | {
+    History: {
+      spins: SpinResult[];
+    };
+  }
+  | {
+    SomePlayerJoined: {
+      bet_amount: number;
+      hash_id: string;
+      name: string;
+    };
+  }
+  | {
+    SomePlayerLeft: {
+      hash_id: string;
+    };
+  }
+  | {
+    SomePlayerPresence: {
+      away: boolean;
+      hash_id: string;
+    };
+  }
+  | {
+    SomePlayerBet: {
+      bet: Bet;
+      hash_id: string;
+    };
+  }
+  | {
+    SomePlayerRemovedBet: {
+      bet: Bet;
+      hash_id: string;
+    };
+  }
+  | {
+    SomePlayerClearedBets: {
+      hash_id: string;
+    };
+  }
+  | {
+    TableClosed: {
+      table_id: string;
+    };
+  }
+  | {
+    ListPlayers: {
+      players: Player[];
+    };
+  }
+  | {
+    Error: {
+      code: ErrorCode;
+      msg: string;
+    };
+  };
+
+export type RoundState = "Idle" | "BettingOpen" | "NoMoreBets" | "Spinning" | "Settling";
+
+/**
+ * A player as everyone at the table sees them.
+ */
+export type SeatedPlayer = {
+  away: boolean;
+  bets: Bet[];
+  id_hash: string;
+  name: string;
+  spin_requested: boolean;
+};
+
+/**
+ * Spins that landed in each part of the racetrack.
+ */
+export type SectorHits = {
+  orphelins: number;
+  tiers_du_cylindre: number;
+  voisins_du_zero: number;
+};
+
+/**
+ * A `V1` table event, its `seq` next to the message.
+ */
+export type SequencedMessage = ResponseMessages & {
+  seq: number;
+};
+
+export type SpinProof = {
+  client_seeds: string[];
+  nonce: number;
+  server_seed: string;
+  server_seed_hash: string;
+  variant: Variant;
+};
+
+/**
+ * How a past round came out.
+ */
+export type SpinResult = {
+  color: PocketColor;
+  lucky_number: number;
+  /**
+   * Missing if the round couldn't be saved.
+   */
+  round_id?: number | null;
+  timestamp: number;
+};
+
+export type StakeLimit = {
+  max?: number | null;
+  min?: number | null;
+};
+
+/**
+ * What the last `spins` spins of a table came out as. Counts are out of `spins`.
+ */
+export type Statistics = {
+  black: number;
+  /**
+   * Least drawn pockets, least first, including ones never drawn.
+   */
+  cold: PocketHits[];
+  /**
+   * Column 1 first.
+   */
+  columns: number[];
+  /**
+   * Dozen 1 first.
+   */
+  dozens: number[];
+  /**
+   * Most drawn pockets, most first, leaving out ones never drawn.
+   */
+  hot: PocketHits[];
+  longest_streaks: Streaks;
+  red: number;
+  /**
+   * Only for single zero wheels.
+   */
+  sectors?: SectorHits | null;
+  spins: number;
+  /**
+   * 0, and 00 on American wheels.
+   */
+  zero: number;
+};
+
+export type Status = {
+  balance: number;
+  bets: Bet[];
+  round_state: RoundState;
+  spin_requested: boolean;
+};
+
+/**
+ * Most spins in a row that won each even-money bet.
+ */
+export type Streaks = {
+  black: number;
+  even: number;
+  high: number;
+  low: number;
+  odd: number;
+  red: number;
+};
+
+/**
+ * House rules of one table, fixed when the table is created. Anything left out of a request keeps its default, which places no limits.
+ */
+export type TableConfig = {
+  /**
+   * How often bets are shown to the rest of the table. Bets placed in between go out together, 0 shows each one straight away.
+   */
+  bet_broadcast_interval_ms?: number;
+  /**
+   * Stake allowed on a single spot, by bet type. Types without an entry only need a positive amount.
+   */
+  bet_limits?: { [key: string]: StakeLimit };
+  /**
+   * How long the table waits for everyone after the first spin request.
+   */
+  betting_window_secs?: number;
+  /**
+   * Connected players the table seats at once.
+   */
+  max_players?: number | null;
+  starting_balance?: number;
+  /**
+   * Most a player may have on the table in one round.
+   */
+  table_max?: number | null;
+  /**
+   * Least a player must have on the table to request a spin.
+   */
+  table_min?: number | null;
+};
+
+export type TableInfo = {
+  config: TableConfig;
+  table_id: string;
+  variant: Variant;
+};
+
+export type TableSnapshot = {
+  /**
+   * When the wheel spins unless everyone asks for it sooner.
+   */
+  betting_closes?: number | null;
+  players: SeatedPlayer[];
+  /**
+   * Newest first.
+   */
+  recent_lucky_numbers: number[];
+  round_state: RoundState;
+  /**
+   * Resume from here.
+   */
+  seq: number;
+  server_seed_hash: string;
+  /**
+   * The joining player's own view.
+   */
+  status: Status;
+  table: TableInfo;
+};
+
+/**
+ * A ledger entry on the player's wallet, negative when money left it.
+ */
+export type Transaction = {
+  amount: number;
+  id: number;
+  kind: EntryKind;
+  timestamp: number;
+};
+
+export type Variant =
+  | "European"
+  | "American"
+  | {
+    French: {
+      rule: ZeroRule;
+    };
+  };
+
+export type ZeroRule = "LaPartage" | "EnPrison";